@@ -0,0 +1,54 @@
+use criterion::*;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use std::io::Cursor;
+
+fn benchmark(c: &mut Criterion) {
+  const MESSAGE_SIZE: usize = 10 << 20;
+  const FRAGMENT_SIZE: usize = 1 << 10;
+
+  let mut data = Vec::new();
+  let mut scratch = Vec::new();
+  let payload = vec![0x42; FRAGMENT_SIZE];
+  let count = MESSAGE_SIZE / FRAGMENT_SIZE;
+  for i in 0..count {
+    let opcode = if i == 0 {
+      OpCode::Binary
+    } else {
+      OpCode::Continuation
+    };
+    let mut frame =
+      Frame::new(i == count - 1, opcode, None, payload[..].into(), false);
+    data.extend_from_slice(frame.write(&mut scratch));
+  }
+
+  let rt = tokio::runtime::Builder::new_current_thread()
+    .build()
+    .unwrap();
+  let mut group = c.benchmark_group("fragments");
+  group.throughput(Throughput::Bytes(MESSAGE_SIZE as u64));
+  for size_hint in [0, MESSAGE_SIZE] {
+    let mut buf = Vec::new();
+    group.bench_function(format!("10 MiB in 1 KiB, hint {size_hint}"), |b| {
+      b.iter(|| {
+        rt.block_on(async {
+          let ws = WebSocket::after_handshake(
+            Cursor::new(&mut data[..]),
+            Role::Client,
+          );
+          let mut ws = FragmentCollector::new(ws);
+          ws.set_message_size_hint(size_hint);
+          ws.read_message_into(&mut buf).await.unwrap();
+          assert_eq!(black_box(&buf).len(), MESSAGE_SIZE);
+        })
+      });
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, benchmark);
+criterion_main!(benches);
@@ -19,6 +19,8 @@
 // limitations under the License.
 
 use self::CloseCode::*;
+use crate::WebSocketError;
+
 /// Status code used to indicate why an endpoint is closing the WebSocket connection.
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum CloseCode {
@@ -80,6 +82,9 @@ pub enum CloseCode {
   /// to a different IP (when multiple targets exist), or reconnect to the same IP
   /// when a user has performed an action.
   Again,
+  /// Indicates that the server was acting as a gateway or proxy and received an
+  /// invalid response from the upstream server.
+  BadGateway,
   #[doc(hidden)]
   Tls,
   #[doc(hidden)]
@@ -93,9 +98,87 @@ pub enum CloseCode {
 }
 
 impl CloseCode {
+  /// 1000, see [`CloseCode::Normal`].
+  pub const NORMAL: CloseCode = Normal;
+  /// 1001, see [`CloseCode::Away`].
+  pub const GOING_AWAY: CloseCode = Away;
+  /// 1002, see [`CloseCode::Protocol`].
+  pub const PROTOCOL_ERROR: CloseCode = Protocol;
+  /// 1003, see [`CloseCode::Unsupported`].
+  pub const UNSUPPORTED_DATA: CloseCode = Unsupported;
+  /// 1005, see [`CloseCode::Status`].
+  pub const NO_STATUS_RECEIVED: CloseCode = Status;
+  /// 1006, see [`CloseCode::Abnormal`].
+  pub const ABNORMAL_CLOSURE: CloseCode = Abnormal;
+  /// 1007, see [`CloseCode::Invalid`].
+  pub const INVALID_PAYLOAD: CloseCode = Invalid;
+  /// 1008, see [`CloseCode::Policy`].
+  pub const POLICY_VIOLATION: CloseCode = Policy;
+  /// 1009, see [`CloseCode::Size`].
+  pub const MESSAGE_TOO_BIG: CloseCode = Size;
+  /// 1010, see [`CloseCode::Extension`].
+  pub const MANDATORY_EXTENSION: CloseCode = Extension;
+  /// 1011, see [`CloseCode::Error`].
+  pub const INTERNAL_ERROR: CloseCode = Error;
+  /// 1012, see [`CloseCode::Restart`].
+  pub const SERVICE_RESTART: CloseCode = Restart;
+  /// 1013, see [`CloseCode::Again`].
+  pub const TRY_AGAIN_LATER: CloseCode = Again;
+  /// 1014, see [`CloseCode::BadGateway`].
+  pub const BAD_GATEWAY: CloseCode = BadGateway;
+  /// 1015, TLS handshake failure.
+  pub const TLS_HANDSHAKE: CloseCode = Tls;
+
+  /// Returns the numeric value of the close code.
+  pub fn as_u16(self) -> u16 {
+    self.into()
+  }
+
+  /// Check if this CloseCode is reserved by the protocol: either not assigned yet (1016-2999), or only
+  /// meant to be reported locally and never sent in a close frame (1005, 1006 and 1015).
+  pub fn is_reserved(self) -> bool {
+    matches!(self, Reserved(_) | Status | Abnormal | Tls)
+  }
+
+  /// Check if this CloseCode is in the range registered with IANA for use by libraries, frameworks
+  /// and applications (3000-3999).
+  pub fn is_library(self) -> bool {
+    matches!(self, Iana(_))
+  }
+
+  /// Check if this CloseCode is in the range for private use (4000-4999).
+  pub fn is_private_use(self) -> bool {
+    matches!(self, Library(_))
+  }
+
   /// Check if this CloseCode is allowed.
   pub fn is_allowed(self) -> bool {
-    !matches!(self, Bad(_) | Reserved(_) | Status | Abnormal | Tls)
+    !self.is_reserved() && !matches!(self, Bad(_))
+  }
+
+  /// Returns a human-readable description of the close code, for logging.
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Normal => "normal closure",
+      Away => "going away",
+      Protocol => "protocol error",
+      Unsupported => "unsupported data",
+      Status => "no status received",
+      Abnormal => "abnormal closure",
+      Invalid => "invalid frame payload data",
+      Policy => "policy violation",
+      Size => "message too big",
+      Extension => "mandatory extension",
+      Error => "internal error",
+      Restart => "service restart",
+      Again => "try again later",
+      BadGateway => "bad gateway",
+      Tls => "TLS handshake failure",
+      Reserved(_) => "reserved",
+      Iana(_) => "registered",
+      Library(_) => "private use",
+      Bad(_) => "invalid close code",
+    }
   }
 }
 
@@ -115,6 +198,7 @@ impl From<u16> for CloseCode {
       1011 => Error,
       1012 => Restart,
       1013 => Again,
+      1014 => BadGateway,
       1015 => Tls,
       1..=999 => Bad(code),
       1016..=2999 => Reserved(code),
@@ -141,6 +225,7 @@ impl From<CloseCode> for u16 {
       Error => 1011,
       Restart => 1012,
       Again => 1013,
+      BadGateway => 1014,
       Tls => 1015,
       Reserved(code) => code,
       Iana(code) => code,
@@ -149,3 +234,132 @@ impl From<CloseCode> for u16 {
     }
   }
 }
+
+/// The close frame sent in reply to the peer's close frame when auto-close is enabled.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum CloseReply {
+  /// Echo the peer's close code and reason.
+  Echo,
+  /// Reply with the given close code and reason.
+  Code(CloseCode, String),
+  /// Reply with an empty close frame.
+  Empty,
+}
+
+/// State of the closing handshake of a connection.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum CloseState {
+  /// No close frame has been sent or received.
+  Open,
+  /// A close frame has been sent, the peer's close frame is still expected.
+  ClosingSent,
+  /// A close frame has been received but none has been sent in reply yet.
+  ClosingReceived,
+  /// Close frames have been both sent and received.
+  Closed,
+}
+
+impl CloseState {
+  pub(crate) fn new(sent: bool, received: bool) -> Self {
+    match (sent, received) {
+      (false, false) => CloseState::Open,
+      (true, false) => CloseState::ClosingSent,
+      (false, true) => CloseState::ClosingReceived,
+      (true, true) => CloseState::Closed,
+    }
+  }
+}
+
+/// Close frame payload validation.
+pub struct CloseFrame;
+
+impl CloseFrame {
+  /// Parses and validates a close frame payload.
+  ///
+  /// Checks the payload length, that the reason is valid UTF-8 and that the
+  /// close code is allowed to be sent over the wire. An empty payload yields
+  /// `CloseCode::Status` with an empty reason.
+  ///
+  /// This is the same validation performed when auto-close is enabled.
+  pub fn parse(payload: &[u8]) -> Result<(CloseCode, &str), WebSocketError> {
+    match payload.len() {
+      0 => Ok((Status, "")),
+      1 => Err(WebSocketError::InvalidCloseFrame),
+      _ => {
+        let code =
+          CloseCode::from(u16::from_be_bytes([payload[0], payload[1]]));
+
+        let reason = crate::frame::from_utf8(&payload[2..])
+          .ok_or(WebSocketError::InvalidUTF8)?;
+
+        if !code.is_allowed() {
+          return Err(WebSocketError::InvalidCloseCode);
+        }
+
+        Ok((code, reason))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn service_restart_codes() {
+    for (code, close_code) in
+      [(1012, Restart), (1013, Again), (1014, BadGateway)]
+    {
+      assert_eq!(CloseCode::from(code), close_code);
+      assert_eq!(u16::from(close_code), code);
+      assert!(close_code.is_allowed());
+    }
+    assert_eq!(CloseCode::from(1013).as_str(), "try again later");
+  }
+
+  #[test]
+  fn round_trip() {
+    for code in 0..=u16::MAX {
+      assert_eq!(CloseCode::from(code).as_u16(), code);
+    }
+  }
+
+  #[test]
+  fn classification() {
+    assert_eq!(CloseCode::from(1001), CloseCode::GOING_AWAY);
+    assert!(matches!(CloseCode::from(1009), CloseCode::MESSAGE_TOO_BIG));
+
+    assert!(CloseCode::from(1005).is_reserved());
+    assert!(CloseCode::from(2000).is_reserved());
+    assert!(!CloseCode::NORMAL.is_reserved());
+
+    assert!(CloseCode::from(3000).is_library());
+    assert!(!CloseCode::from(4000).is_library());
+    assert!(CloseCode::from(4999).is_private_use());
+
+    assert!(CloseCode::from(3000).is_allowed());
+    assert!(!CloseCode::from(999).is_allowed());
+  }
+
+  #[test]
+  fn parse_close_frame() {
+    assert!(matches!(CloseFrame::parse(&[]), Ok((Status, ""))));
+    assert!(matches!(
+      CloseFrame::parse(&[0x03]),
+      Err(WebSocketError::InvalidCloseFrame)
+    ));
+    assert!(matches!(
+      CloseFrame::parse(b"\x03\xe8bye"),
+      Ok((Normal, "bye"))
+    ));
+    assert!(matches!(
+      CloseFrame::parse(b"\x03\xe8\xff"),
+      Err(WebSocketError::InvalidUTF8)
+    ));
+    assert!(matches!(
+      CloseFrame::parse(b"\x03\xed"),
+      Err(WebSocketError::InvalidCloseCode)
+    ));
+  }
+}
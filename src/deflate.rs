@@ -0,0 +1,187 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! permessage-deflate codec.
+//!
+//! The rest of the crate only goes through `Deflate` and `Inflate`, the backend implementing them is
+//! created by `new_deflater` and `new_inflater`. miniz_oxide is the only backend, there is no feature
+//! to select another one such as zlib.
+
+use miniz_oxide::deflate::core::create_comp_flags_from_zip_params;
+use miniz_oxide::deflate::core::CompressorOxide;
+use miniz_oxide::deflate::stream::deflate;
+use miniz_oxide::inflate::stream::inflate;
+use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::DataFormat;
+use miniz_oxide::MZError;
+use miniz_oxide::MZFlush;
+use miniz_oxide::MZStatus;
+
+use crate::WebSocketError;
+
+/// Ends every sync flushed block, it is removed from the end of compressed messages.
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Compresses the messages of a connection as one raw deflate stream.
+pub(crate) trait Deflate: Send + Sync {
+  /// Compresses `data` with a sync flush, continuing the stream.
+  ///
+  /// The trailing 0x00 0x00 0xff 0xff is removed when `fin` is set, as it marks
+  /// the end of a message.
+  fn deflate(
+    &mut self,
+    data: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError>;
+
+  /// Changes the compression level, from 0 to 9, for the data compressed next.
+  fn set_level(&mut self, level: u8);
+
+  /// Starts a new stream, not referring to any data compressed before.
+  fn reset(&mut self);
+}
+
+/// Inflates the messages of a connection as one raw deflate stream.
+pub(crate) trait Inflate: Send + Sync {
+  /// Inflates `data` to the end of `out`, continuing the stream. `fin` marks
+  /// the end of a message.
+  ///
+  /// The spare capacity of `out` is used before it is grown. Returns
+  /// `WebSocketError::FrameTooLarge` as soon as more than `max_size` bytes are
+  /// appended.
+  fn inflate(
+    &mut self,
+    data: &[u8],
+    fin: bool,
+    max_size: usize,
+    out: &mut Vec<u8>,
+  ) -> Result<(), WebSocketError>;
+}
+
+/// Creates a compressor with the given level.
+pub(crate) fn new_deflater(level: u8) -> Box<dyn Deflate> {
+  Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(
+    level as i32,
+    -15,
+    0,
+  )))
+}
+
+/// Creates a decompressor.
+pub(crate) fn new_inflater() -> Box<dyn Inflate> {
+  InflateState::new_boxed(DataFormat::Raw)
+}
+
+impl Deflate for CompressorOxide {
+  fn deflate(
+    &mut self,
+    data: &[u8],
+    fin: bool,
+  ) -> Result<Vec<u8>, WebSocketError> {
+    let mut input = data;
+    let mut out: Vec<u8> = Vec::new();
+    let mut written = 0;
+
+    loop {
+      if written == out.len() {
+        let len = out.len().saturating_mul(2).max(data.len() / 2 + 64);
+        out.resize(len, 0);
+      }
+
+      let res = deflate(self, input, &mut out[written..], MZFlush::Sync);
+      input = &input[res.bytes_consumed..];
+      written += res.bytes_written;
+
+      match res.status {
+        // All input consumed and the output was not filled, the flush is complete.
+        Ok(_) if input.is_empty() && written < out.len() => break,
+        Ok(_) => {}
+        Err(MZError::Buf) if input.is_empty() => break,
+        Err(_) => return Err(WebSocketError::CompressionFailed),
+      }
+    }
+
+    out.truncate(written);
+    if fin && out.ends_with(&TRAILER) {
+      out.truncate(out.len() - TRAILER.len());
+    }
+    Ok(out)
+  }
+
+  fn set_level(&mut self, level: u8) {
+    self.set_compression_level_raw(level);
+  }
+
+  fn reset(&mut self) {
+    CompressorOxide::reset(self);
+  }
+}
+
+impl Inflate for InflateState {
+  fn inflate(
+    &mut self,
+    data: &[u8],
+    fin: bool,
+    max_size: usize,
+    out: &mut Vec<u8>,
+  ) -> Result<(), WebSocketError> {
+    // The trailer is fed after the payload rather than copied onto it.
+    let trailer: &[u8] = if fin { &TRAILER } else { &[] };
+    let start = out.len();
+    let limit = start.saturating_add(max_size);
+    let mut written = start;
+
+    let res = 'stream: {
+      for mut input in [data, trailer] {
+        loop {
+          if written == out.len() {
+            if written > limit {
+              break 'stream Err(WebSocketError::FrameTooLarge);
+            }
+            // Never grow past one byte over the limit, that is enough to detect overflow.
+            let len = out
+              .len()
+              .saturating_mul(2)
+              .max(start + (data.len() + trailer.len()).saturating_mul(2))
+              .max(out.capacity())
+              .min(limit.saturating_add(1));
+            out.resize(len, 0);
+          }
+
+          let res = inflate(self, input, &mut out[written..], MZFlush::None);
+          input = &input[res.bytes_consumed..];
+          written += res.bytes_written;
+
+          match res.status {
+            Ok(MZStatus::StreamEnd) => break 'stream Ok(()),
+            // All input consumed and the output was not filled, nothing is pending.
+            Ok(_) if input.is_empty() && written < out.len() => break,
+            Ok(_) => {}
+            // No progress can be made without more input.
+            Err(MZError::Buf) if input.is_empty() => break,
+            Err(_) => break 'stream Err(WebSocketError::InvalidEncoding),
+          }
+        }
+      }
+      Ok(())
+    };
+
+    out.truncate(written);
+    res?;
+    if written > limit {
+      return Err(WebSocketError::FrameTooLarge);
+    }
+    Ok(())
+  }
+}
@@ -2,12 +2,20 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum WebSocketError {
+  #[deprecated(
+    note = "never returned, fragmentation errors are `InvalidFragmentSequence`"
+  )]
   #[error("Invalid fragment")]
   InvalidFragment,
   #[error("Invalid UTF-8")]
   InvalidUTF8,
+  #[deprecated(
+    note = "never returned, fragmentation errors are `InvalidFragmentSequence`"
+  )]
   #[error("Invalid continuation frame")]
   InvalidContinuationFrame,
+  #[error("Invalid fragment sequence")]
+  InvalidFragmentSequence,
   #[error("Invalid status code: {0}")]
   InvalidStatusCode(u16),
   #[error("Invalid upgrade header")]
@@ -22,22 +30,61 @@ pub enum WebSocketError {
   InvalidCloseCode,
   #[error("Unexpected EOF")]
   UnexpectedEOF,
+  #[error("Connection closed by peer without a close frame")]
+  ConnectionReset,
+  #[error("Timed out reading a frame")]
+  ReadTimeout,
+  #[error("Timed out writing a frame")]
+  WriteTimeout,
+  #[error("Connection idle for too long")]
+  IdleTimeout,
+  #[error("Timed out assembling a fragmented message")]
+  MessageTimeout,
   #[error("Reserved bits are not zero")]
   ReservedBitsNotZero,
   #[error("Control frame must not be fragmented")]
   ControlFrameFragmented,
+  #[deprecated(
+    note = "never returned, control frames over 125 bytes fail with `ControlFrameTooLarge`"
+  )]
   #[error("Ping frame too large")]
   PingFrameTooLarge,
+  #[error("Control frame too large")]
+  ControlFrameTooLarge,
   #[error("Frame too large")]
   FrameTooLarge,
+  #[error("Message split into too many fragments")]
+  TooManyFragments,
+  #[error("Frame header received in too many reads")]
+  TooManyHeaderReads,
+  #[error("Rate limit exceeded")]
+  RateLimitExceeded,
+  #[error("Too many pongs waiting to be written")]
+  TooManyPendingPongs,
   #[error("Sec-Websocket-Version must be 13")]
   InvalidSecWebsocketVersion,
   #[error("Invalid value")]
   InvalidValue,
   #[error("Invalid encoding")]
   InvalidEncoding,
+  #[error("Failed to compress payload")]
+  CompressionFailed,
+  #[error("Invalid Sec-WebSocket-Extensions header")]
+  InvalidExtension,
+  #[error("Invalid HTTP upgrade request")]
+  InvalidUpgradeRequest,
+  #[error("Invalid HTTP upgrade response")]
+  InvalidUpgradeResponse,
+  #[error("Sec-WebSocket-Accept header does not match the key")]
+  InvalidSecWebSocketAccept,
   #[error("Sec-WebSocket-Key header is missing")]
   MissingSecWebSocketKey,
+  #[error("Origin header is missing or not allowed")]
+  InvalidOrigin,
+  #[error("Header {0} is set by the handshake")]
+  ReservedHeader(String),
+  #[error("Frame rejected with close code {0:?}")]
+  FrameRejected(crate::CloseCode),
   #[error(transparent)]
   IoError(#[from] std::io::Error),
   #[cfg(feature = "upgrade")]
@@ -0,0 +1,193 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::WebSocketError;
+
+/// The extensions agreed on during the handshake, with their parameters.
+///
+/// permessage-deflate (RFC 7692) is supported, as well as the legacy x-webkit-deflate-frame extension of old
+/// WebKit clients with the `deflate-frame` feature.
+///
+/// The window bits are the values negotiated in the handshake, not what is allocated: every compressor and
+/// decompressor keeps a 32 KiB window, the size for 15 bits, whatever was negotiated. A client fails the
+/// handshake if the server asks it to compress with a smaller window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtensionConfig {
+  /// Whether permessage-deflate is in use.
+  pub permessage_deflate: bool,
+  /// The server resets its compression context after each message.
+  pub server_no_context_takeover: bool,
+  /// The client resets its compression context after each message.
+  pub client_no_context_takeover: bool,
+  /// The window bits negotiated for the messages the server compresses, from 8 to 15.
+  pub server_max_window_bits: u8,
+  /// The window bits negotiated for the messages the client compresses, from 8 to 15.
+  pub client_max_window_bits: u8,
+  /// Whether the legacy x-webkit-deflate-frame extension is in use. It shares the deflate codec, so
+  /// `permessage_deflate` is set along with it, but every frame is compressed on its own and has RSV1 set,
+  /// continuation frames included.
+  pub deflate_frame: bool,
+}
+
+impl Default for ExtensionConfig {
+  fn default() -> Self {
+    Self {
+      permessage_deflate: false,
+      server_no_context_takeover: false,
+      client_no_context_takeover: false,
+      server_max_window_bits: 15,
+      client_max_window_bits: 15,
+      deflate_frame: false,
+    }
+  }
+}
+
+impl ExtensionConfig {
+  /// Parses the `Sec-WebSocket-Extensions` header of a server handshake response.
+  ///
+  /// Returns `WebSocketError::InvalidExtension` for unknown extensions or parameters, as the
+  /// connection must then be failed.
+  pub fn parse(header: &str) -> Result<Self, WebSocketError> {
+    let mut config = Self::default();
+
+    for extension in header.split(',').map(str::trim) {
+      if extension.is_empty() {
+        continue;
+      }
+
+      let mut params = extension.split(';').map(str::trim);
+      let name = params.next();
+      if config.permessage_deflate {
+        return Err(WebSocketError::InvalidExtension);
+      }
+      #[cfg(feature = "deflate-frame")]
+      if name == Some("x-webkit-deflate-frame") {
+        // Parameters are not supported, the extension is used with its
+        // defaults.
+        if params.next().is_some() {
+          return Err(WebSocketError::InvalidExtension);
+        }
+        config.permessage_deflate = true;
+        config.deflate_frame = true;
+        continue;
+      }
+      if name != Some("permessage-deflate") {
+        return Err(WebSocketError::InvalidExtension);
+      }
+      config.permessage_deflate = true;
+
+      for param in params {
+        let (name, value) = match param.split_once('=') {
+          Some((name, value)) => {
+            (name.trim(), Some(value.trim().trim_matches('"')))
+          }
+          None => (param, None),
+        };
+
+        match (name, value) {
+          ("server_no_context_takeover", None) => {
+            config.server_no_context_takeover = true;
+          }
+          ("client_no_context_takeover", None) => {
+            config.client_no_context_takeover = true;
+          }
+          ("server_max_window_bits", Some(bits)) => {
+            config.server_max_window_bits = parse_window_bits(bits)?;
+          }
+          ("client_max_window_bits", Some(bits)) => {
+            config.client_max_window_bits = parse_window_bits(bits)?;
+          }
+          _ => return Err(WebSocketError::InvalidExtension),
+        }
+      }
+    }
+
+    Ok(config)
+  }
+}
+
+fn parse_window_bits(bits: &str) -> Result<u8, WebSocketError> {
+  match bits.parse() {
+    Ok(bits @ 8..=15) => Ok(bits),
+    _ => Err(WebSocketError::InvalidExtension),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse() {
+    assert_eq!(
+      ExtensionConfig::parse("").unwrap(),
+      ExtensionConfig::default()
+    );
+
+    let config = ExtensionConfig::parse(
+      "permessage-deflate; server_no_context_takeover; client_max_window_bits=10",
+    )
+    .unwrap();
+    assert_eq!(
+      config,
+      ExtensionConfig {
+        permessage_deflate: true,
+        server_no_context_takeover: true,
+        client_no_context_takeover: false,
+        server_max_window_bits: 15,
+        client_max_window_bits: 10,
+        deflate_frame: false,
+      }
+    );
+
+    let config =
+      ExtensionConfig::parse("permessage-deflate;server_max_window_bits=\"9\"")
+        .unwrap();
+    assert_eq!(config.server_max_window_bits, 9);
+  }
+
+  #[cfg(feature = "deflate-frame")]
+  #[test]
+  fn parse_deflate_frame() {
+    let config = ExtensionConfig::parse("x-webkit-deflate-frame").unwrap();
+    assert!(config.permessage_deflate && config.deflate_frame);
+
+    for header in [
+      "x-webkit-deflate-frame; no_context_takeover",
+      "x-webkit-deflate-frame, permessage-deflate",
+    ] {
+      assert!(matches!(
+        ExtensionConfig::parse(header),
+        Err(WebSocketError::InvalidExtension)
+      ));
+    }
+  }
+
+  #[test]
+  fn parse_invalid() {
+    for header in [
+      #[cfg(not(feature = "deflate-frame"))]
+      "x-webkit-deflate-frame",
+      "permessage-deflate, permessage-deflate",
+      "permessage-deflate; server_max_window_bits=16",
+      "permessage-deflate; server_max_window_bits",
+      "permessage-deflate; unknown",
+    ] {
+      assert!(matches!(
+        ExtensionConfig::parse(header),
+        Err(WebSocketError::InvalidExtension)
+      ));
+    }
+  }
+}
@@ -14,17 +14,39 @@
 
 #[cfg(feature = "unstable-split")]
 use std::future::Future;
+use std::time::Duration;
 
+use crate::close::CloseCode;
+use crate::close::CloseState;
 use crate::error::WebSocketError;
+use crate::extension::ExtensionConfig;
+use crate::frame::CompressionStats;
+use crate::frame::Event;
 use crate::frame::Frame;
+use crate::frame::Payload;
+use crate::frame::WriteStats;
+use crate::MessageBuilder;
 use crate::OpCode;
 use crate::ReadHalf;
+use crate::Role;
 use crate::WebSocket;
 #[cfg(feature = "unstable-split")]
 use crate::WebSocketRead;
 use crate::WriteHalf;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
+use tokio::time::Instant;
+
+/// Returns the code of the close frame sent when assembling a message fails with `e`, if any.
+fn close_code(e: &WebSocketError) -> Option<CloseCode> {
+  match e {
+    WebSocketError::TooManyFragments | WebSocketError::FrameTooLarge => {
+      Some(CloseCode::Size)
+    }
+    WebSocketError::InvalidFragmentSequence => Some(CloseCode::Protocol),
+    _ => None,
+  }
+}
 
 pub enum Fragment {
   Text(Option<utf8::Incomplete>, Vec<u8>),
@@ -32,6 +54,14 @@ pub enum Fragment {
 }
 
 impl Fragment {
+  /// Returns the length of the payload received so far.
+  fn len(&self) -> usize {
+    match self {
+      Fragment::Text(_, buffer) => buffer.len(),
+      Fragment::Binary(buffer) => buffer.len(),
+    }
+  }
+
   /// Returns the payload of the fragment.
   fn take_buffer(self) -> Vec<u8> {
     match self {
@@ -99,31 +129,172 @@ impl<'f, S> FragmentCollector<S> {
   /// Reads a WebSocket frame, collecting fragmented messages until the final frame is received and returns the completed message.
   ///
   /// Text frames payload is guaranteed to be valid UTF-8.
+  ///
+  /// Like `WebSocket::read_frame`, messages from the peer are still returned after a close frame has been sent.
+  ///
+  /// It is cancel safe like `WebSocket::read_frame`, the fragments already received are kept.
   pub async fn read_frame(&mut self) -> Result<Frame<'f>, WebSocketError>
   where
     S: AsyncRead + AsyncWrite + Unpin,
   {
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+
     loop {
-      let (res, obligated_send) =
-        self.read_half.read_frame_inner(&mut self.stream).await;
-      let is_closed = self.write_half.closed;
+      let deadline = self.fragments.deadline();
+      let read = self
+        .read_half
+        .read_frame_idle(&mut self.stream, self.write_half.last_write);
+      let (res, obligated_send) = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, read)
+          .await
+          .unwrap_or_else(|_| {
+            self.fragments.timed_out(self.read_half.auto_close)
+          }),
+        None => read.await,
+      };
       if let Some(obligated_send) = obligated_send {
-        if !is_closed {
-          self.write_frame(obligated_send).await?;
+        if !self.write_half.closed {
+          self
+            .write_half
+            .write_obligated(&mut self.stream, obligated_send)
+            .await?;
         }
       }
       let Some(frame) = res? else {
         continue;
       };
-      if is_closed && frame.opcode != OpCode::Close {
-        return Err(WebSocketError::ConnectionClosed);
+      match self.fragments.accumulate(
+        frame,
+        self.read_half.validate_text,
+        self.read_half.role == Role::Server && !self.read_half.auto_apply_mask,
+        |opcode| self.read_half.max_size(opcode),
+      ) {
+        Ok(Some(frame)) => return Ok(frame),
+        Ok(None) => {}
+        Err(e) => {
+          if let Some(code) = close_code(&e) {
+            if self.read_half.auto_close && !self.write_half.closed {
+              self.write_frame(Frame::close(code.into(), &[])).await?;
+            }
+          }
+          return Err(e);
+        }
+      }
+    }
+  }
+
+  /// Reads a message like `read_frame` and returns it as an `Event`, see `WebSocket::read_event`.
+  pub async fn read_event(&mut self) -> Result<Event<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    Event::from_frame(self.read_frame().await?)
+  }
+
+  /// Sets the maximum number of frames a message can be split into. Longer messages fail with
+  /// `WebSocketError::TooManyFragments`, and the connection is closed with code 1009 if auto close is
+  /// enabled.
+  ///
+  /// This bounds the cost of a message sent as many tiny fragments, which the size limits do not.
+  ///
+  /// Default: `None`, no limit
+  pub fn set_max_fragments(&mut self, max_fragments: Option<usize>) {
+    self.fragments.max_fragments = max_fragments;
+  }
+
+  /// Sets the maximum time to receive all the fragments of a message, counted from its first fragment.
+  /// When it expires, `read_frame` fails with `WebSocketError::MessageTimeout` and the connection is closed
+  /// with code 1008 if auto close is enabled.
+  ///
+  /// Unlike the idle timeout, this bounds peers that keep a message open by sending fragments slowly.
+  ///
+  /// Default: `None`, no limit
+  pub fn set_message_timeout(&mut self, message_timeout: Option<Duration>) {
+    self.fragments.message_timeout = message_timeout;
+  }
+
+  /// Sets the expected size of fragmented messages. The buffer a message is assembled into is reserved for
+  /// this size, or the size of the first fragment if larger, when the message starts, instead of growing
+  /// as the fragments arrive.
+  ///
+  /// Default: 0, the buffer grows as needed
+  pub fn set_message_size_hint(&mut self, size_hint: usize) {
+    self.fragments.size_hint = size_hint;
+  }
+
+  /// Reads the next Text or Binary message into `buf` and returns its opcode.
+  ///
+  /// `buf` is cleared first and fragmented messages are assembled directly into it, so reading into the
+  /// same buffer in a loop reuses its allocation. Ping and pong frames are skipped, and a close frame ends
+  /// the read with `WebSocketError::ConnectionClosed` like `read_message`.
+  pub async fn read_message_into(
+    &mut self,
+    buf: &mut Vec<u8>,
+  ) -> Result<OpCode, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    // A message already being assembled keeps its own buffer.
+    if self.fragments.fragments.is_none() {
+      self.fragments.spare = std::mem::take(buf);
+    }
+    loop {
+      let frame = self.read_frame().await?;
+      match frame.opcode {
+        OpCode::Text | OpCode::Binary => {
+          match frame.payload {
+            Payload::Owned(payload) => *buf = payload,
+            payload => {
+              *buf = std::mem::take(&mut self.fragments.spare);
+              buf.clear();
+              buf.extend_from_slice(&payload);
+            }
+          }
+          return Ok(frame.opcode);
+        }
+        OpCode::Close => return Err(WebSocketError::ConnectionClosed),
+        _ => {}
       }
-      if let Some(frame) = self.fragments.accumulate(frame)? {
-        return Ok(frame);
+    }
+  }
+
+  /// Reads the next Text or Binary message and returns its opcode and owned payload.
+  ///
+  /// Text payloads are valid UTF-8. Ping and pong frames are skipped, and a close frame ends the
+  /// read with `WebSocketError::ConnectionClosed` once it has been replied to.
+  pub async fn read_message(
+    &mut self,
+  ) -> Result<(OpCode, Vec<u8>), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    loop {
+      let frame = self.read_frame().await?;
+      match frame.opcode {
+        OpCode::Text | OpCode::Binary => {
+          return Ok((frame.opcode, frame.payload.into()));
+        }
+        OpCode::Close => return Err(WebSocketError::ConnectionClosed),
+        _ => {}
       }
     }
   }
 
+  /// See `WebSocket::read_frame_deadline`. Fragments received before the deadline are kept.
+  pub async fn read_frame_deadline(
+    &mut self,
+    deadline: Instant,
+  ) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    tokio::time::timeout_at(deadline, self.read_frame())
+      .await
+      .map_err(|_| WebSocketError::ReadTimeout)?
+  }
+
   /// See `WebSocket::write_frame`.
   pub async fn write_frame(
     &mut self,
@@ -136,11 +307,140 @@ impl<'f, S> FragmentCollector<S> {
     Ok(())
   }
 
+  /// See `WebSocket::write_raw`.
+  pub async fn write_raw(&mut self, bytes: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self.write_half.write_raw(&mut self.stream, bytes).await
+  }
+
+  /// See `WebSocket::write_frame_with_progress`.
+  pub async fn write_frame_with_progress<F>(
+    &mut self,
+    frame: Frame<'_>,
+    chunk_size: usize,
+    progress: F,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(usize, usize),
+  {
+    self
+      .write_half
+      .write_frame_with_progress(&mut self.stream, frame, chunk_size, progress)
+      .await
+  }
+
+  /// See `WebSocket::write_frame_deadline`.
+  pub async fn write_frame_deadline(
+    &mut self,
+    frame: Frame<'f>,
+    deadline: Instant,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    tokio::time::timeout_at(deadline, self.write_frame(frame))
+      .await
+      .map_err(|_| WebSocketError::WriteTimeout)?
+  }
+
+  /// See `WebSocket::write_message`.
+  pub async fn write_message(
+    &mut self,
+    message: MessageBuilder<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .write_message(&mut self.stream, message)
+      .await
+  }
+
+  /// See `WebSocket::send_text`.
+  pub async fn send_text(&mut self, text: &str) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let compress = self.read_half.extensions.permessage_deflate;
+    self
+      .write_half
+      .send(&mut self.stream, OpCode::Text, text.as_bytes(), compress)
+      .await
+  }
+
+  /// See `WebSocket::send_binary`.
+  pub async fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let compress = self.read_half.extensions.permessage_deflate;
+    self
+      .write_half
+      .send(&mut self.stream, OpCode::Binary, data, compress)
+      .await
+  }
+
+  /// See `WebSocket::is_closed`.
+  pub fn is_closed(&self) -> bool {
+    self.write_half.closed
+  }
+
+  /// See `WebSocket::close_state`.
+  pub fn close_state(&self) -> CloseState {
+    CloseState::new(self.write_half.closed, self.read_half.close_received)
+  }
+
+  /// See `WebSocket::negotiated_extensions`.
+  pub fn negotiated_extensions(&self) -> &ExtensionConfig {
+    &self.read_half.extensions
+  }
+
+  /// See `WebSocket::buffered_len`.
+  pub fn buffered_len(&self) -> usize {
+    self.read_half.buffer.len()
+  }
+
+  /// Returns whether a complete frame is buffered. It may be a fragment of a message that is not
+  /// complete yet, see `WebSocket::has_buffered_frame`.
+  pub fn has_buffered_frame(&self) -> bool {
+    self.read_half.has_buffered_frame()
+  }
+
+  /// See `WebSocket::compression_stats`.
+  pub fn compression_stats(&self) -> CompressionStats {
+    CompressionStats {
+      compressed_out: self.write_half.stats.compressed_out,
+      raw_out: self.write_half.stats.raw_out,
+      ..self.read_half.stats
+    }
+  }
+
+  /// See `WebSocket::write_stats`.
+  pub fn write_stats(&self) -> WriteStats {
+    self.write_half.write_stats
+  }
+
   /// Consumes the `FragmentCollector` and returns the underlying stream.
   #[inline]
   pub fn into_inner(self) -> S {
     self.stream
   }
+
+  /// See `WebSocket::get_ref`.
+  #[inline]
+  pub fn get_ref(&self) -> &S {
+    &self.stream
+  }
+
+  /// See `WebSocket::get_mut`.
+  #[inline]
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.stream
+  }
 }
 
 #[cfg(feature = "unstable-split")]
@@ -181,9 +481,21 @@ impl<'f, S> FragmentCollectorRead<S> {
     E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
     R: Future<Output = Result<(), E>>,
   {
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+
     loop {
-      let (res, obligated_send) =
-        self.read_half.read_frame_inner(&mut self.stream).await;
+      let deadline = self.fragments.deadline();
+      let read = self.read_half.read_frame_inner(&mut self.stream);
+      let (res, obligated_send) = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, read)
+          .await
+          .unwrap_or_else(|_| {
+            self.fragments.timed_out(self.read_half.auto_close)
+          }),
+        None => read.await,
+      };
       if let Some(frame) = obligated_send {
         let res = send_fn(frame).await;
         res.map_err(|e| WebSocketError::SendError(e.into()))?;
@@ -191,17 +503,56 @@ impl<'f, S> FragmentCollectorRead<S> {
       let Some(frame) = res? else {
         continue;
       };
-      if let Some(frame) = self.fragments.accumulate(frame)? {
-        return Ok(frame);
+      match self.fragments.accumulate(
+        frame,
+        self.read_half.validate_text,
+        self.read_half.role == Role::Server && !self.read_half.auto_apply_mask,
+        |opcode| self.read_half.max_size(opcode),
+      ) {
+        Ok(Some(frame)) => return Ok(frame),
+        Ok(None) => {}
+        Err(e) => {
+          if let Some(code) = close_code(&e) {
+            if self.read_half.auto_close {
+              let res = send_fn(Frame::close(code.into(), &[])).await;
+              res.map_err(|e| WebSocketError::SendError(e.into()))?;
+            }
+          }
+          return Err(e);
+        }
       }
     }
   }
+
+  /// See `FragmentCollector::set_max_fragments`.
+  pub fn set_max_fragments(&mut self, max_fragments: Option<usize>) {
+    self.fragments.max_fragments = max_fragments;
+  }
+
+  /// See `FragmentCollector::set_message_timeout`.
+  pub fn set_message_timeout(&mut self, message_timeout: Option<Duration>) {
+    self.fragments.message_timeout = message_timeout;
+  }
+
+  /// See `FragmentCollector::set_message_size_hint`.
+  pub fn set_message_size_hint(&mut self, size_hint: usize) {
+    self.fragments.size_hint = size_hint;
+  }
 }
 
 /// Accumulates potentially fragmented [`Frame`]s to defragment the incoming WebSocket stream.
 struct Fragments {
   fragments: Option<Fragment>,
   opcode: OpCode,
+  count: usize,
+  max_fragments: Option<usize>,
+  started: Instant,
+  message_timeout: Option<Duration>,
+  /// The buffer the next fragmented message is assembled into.
+  spare: Vec<u8>,
+  size_hint: usize,
+  /// A fragmented message with a reserved opcode is open, its frames are returned as they are.
+  passthrough: bool,
 }
 
 impl Fragments {
@@ -209,46 +560,144 @@ impl Fragments {
     Self {
       fragments: None,
       opcode: OpCode::Close,
+      count: 0,
+      max_fragments: None,
+      started: Instant::now(),
+      message_timeout: None,
+      spare: Vec::new(),
+      size_hint: 0,
+      passthrough: false,
     }
   }
 
+  /// Returns the buffer to assemble a message starting with `first` into, reserved for the size hint.
+  fn start_buffer(&mut self, first: &[u8]) -> Vec<u8> {
+    let mut buffer = std::mem::take(&mut self.spare);
+    buffer.clear();
+    buffer.reserve(self.size_hint.max(first.len()));
+    buffer.extend_from_slice(first);
+    buffer
+  }
+
+  /// Returns when the message being assembled times out, if any.
+  fn deadline(&self) -> Option<Instant> {
+    self.fragments.as_ref()?;
+    Some(self.started + self.message_timeout?)
+  }
+
+  /// Drops the message that timed out, returning the error and the close frame to send if `auto_close`
+  /// is set.
+  fn timed_out<'f>(
+    &mut self,
+    auto_close: bool,
+  ) -> (Result<Option<Frame<'f>>, WebSocketError>, Option<Frame<'f>>) {
+    self.fragments = None;
+    let close = auto_close.then(|| Frame::close(CloseCode::Policy.into(), &[]));
+    (Err(WebSocketError::MessageTimeout), close)
+  }
+
+  /// Adds a frame to the message being assembled. Text messages are checked to be valid UTF-8 when
+  /// `validate_text` is set. `max_size` returns the size limit of messages of the given type, which
+  /// applies to the assembled payload.
+  ///
+  /// `unmask` is set when frames are read with their payload still masked: the frames of the messages
+  /// assembled here are then unmasked, the frames passed through are left as received.
   pub fn accumulate<'f>(
     &mut self,
-    frame: Frame<'f>,
+    mut frame: Frame<'f>,
+    validate_text: bool,
+    unmask: bool,
+    max_size: impl FnOnce(OpCode) -> usize,
   ) -> Result<Option<Frame<'f>>, WebSocketError> {
+    let data_opcode = |opcode: OpCode| {
+      matches!(opcode, OpCode::Text | OpCode::Binary)
+        || matches!(opcode, OpCode::Other(_)) && !opcode.is_control()
+    };
+    let assembled = match frame.opcode {
+      OpCode::Text | OpCode::Binary => true,
+      OpCode::Continuation => !self.passthrough,
+      _ => false,
+    };
+    if unmask && assembled {
+      frame.unmask();
+    }
     match frame.opcode {
+      // A new message can not start before the previous one is complete.
+      opcode
+        if data_opcode(opcode)
+          && (self.fragments.is_some() || self.passthrough) =>
+      {
+        return Err(WebSocketError::InvalidFragmentSequence);
+      }
+      // Messages with a reserved opcode are not reassembled.
+      OpCode::Other(_) if data_opcode(frame.opcode) => {
+        self.passthrough = !frame.fin;
+        return Ok(Some(frame));
+      }
+      OpCode::Continuation if self.passthrough => {
+        self.passthrough = !frame.fin;
+        return Ok(Some(frame));
+      }
       OpCode::Text | OpCode::Binary => {
         if frame.fin {
-          if self.fragments.is_some() {
-            return Err(WebSocketError::InvalidFragment);
-          }
-          return Ok(Some(Frame::new(true, frame.opcode, None, frame.payload, frame.compressed)));
+          return Ok(Some(Frame::new(
+            true,
+            frame.opcode,
+            None,
+            frame.payload,
+            frame.compressed,
+          )));
         } else {
           self.fragments = match frame.opcode {
-            OpCode::Text => match utf8::decode(&frame.payload) {
-              Ok(text) => Some(Fragment::Text(None, text.as_bytes().to_vec())),
+            OpCode::Text if validate_text => match utf8::decode(&frame.payload)
+            {
+              Ok(text) => {
+                Some(Fragment::Text(None, self.start_buffer(text.as_bytes())))
+              }
               Err(utf8::DecodeError::Incomplete {
                 valid_prefix,
                 incomplete_suffix,
               }) => Some(Fragment::Text(
                 Some(incomplete_suffix),
-                valid_prefix.as_bytes().to_vec(),
+                self.start_buffer(valid_prefix.as_bytes()),
               )),
               Err(utf8::DecodeError::Invalid { .. }) => {
                 return Err(WebSocketError::InvalidUTF8);
               }
             },
-            OpCode::Binary => Some(Fragment::Binary(frame.payload.into())),
+            // Unvalidated text is collected like binary data.
+            OpCode::Text | OpCode::Binary => {
+              Some(Fragment::Binary(self.start_buffer(&frame.payload)))
+            }
             _ => unreachable!(),
           };
           self.opcode = frame.opcode;
+          self.count = 1;
+          self.started = Instant::now();
         }
       }
+      OpCode::Continuation
+        if self.fragments.is_some()
+          && self.max_fragments.is_some_and(|max| self.count >= max) =>
+      {
+        self.fragments = None;
+        return Err(WebSocketError::TooManyFragments);
+      }
+      OpCode::Continuation
+        if self.fragments.as_ref().is_some_and(|fragment| {
+          fragment.len().saturating_add(frame.payload.len())
+            >= max_size(self.opcode)
+        }) =>
+      {
+        self.fragments = None;
+        return Err(WebSocketError::FrameTooLarge);
+      }
       OpCode::Continuation => match self.fragments.as_mut() {
         None => {
-          return Err(WebSocketError::InvalidContinuationFrame);
+          return Err(WebSocketError::InvalidFragmentSequence);
         }
         Some(Fragment::Text(data, input)) => {
+          self.count += 1;
           let mut tail = &frame.payload[..];
           if let Some(mut incomplete) = data.take() {
             if let Some((result, rest)) =
@@ -297,6 +746,7 @@ impl Fragments {
           }
         }
         Some(Fragment::Binary(data)) => {
+          self.count += 1;
           data.extend_from_slice(&frame.payload);
           if frame.fin {
             return Ok(Some(Frame::new(
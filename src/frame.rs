@@ -14,38 +14,16 @@
 
 use tokio::io::AsyncWriteExt;
 
-use miniz_oxide::{MZFlush, MZStatus};
-use miniz_oxide::inflate::stream::{InflateState, inflate};
+use miniz_oxide::inflate::stream::InflateState;
 
+use bytes::Bytes;
 use bytes::BytesMut;
 use core::ops::Deref;
 
+use crate::deflate::Inflate;
+use crate::CloseCode;
 use crate::WebSocketError;
 
-const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
-
-macro_rules! repr_u8 {
-    ($(#[$meta:meta])* $vis:vis enum $name:ident {
-      $($(#[$vmeta:meta])* $vname:ident $(= $val:expr)?,)*
-    }) => {
-      $(#[$meta])*
-      $vis enum $name {
-        $($(#[$vmeta])* $vname $(= $val)?,)*
-      }
-
-      impl core::convert::TryFrom<u8> for $name {
-        type Error = WebSocketError;
-
-        fn try_from(v: u8) -> Result<Self, Self::Error> {
-          match v {
-            $(x if x == $name::$vname as u8 => Ok($name::$vname),)*
-            _ => Err(WebSocketError::InvalidValue),
-          }
-        }
-      }
-    }
-}
-
 pub enum Payload<'a> {
   BorrowedMut(&'a mut [u8]),
   Borrowed(&'a [u8]),
@@ -90,6 +68,26 @@ impl From<Vec<u8>> for Payload<'_> {
   }
 }
 
+impl From<BytesMut> for Payload<'_> {
+  fn from(bytes: BytesMut) -> Self {
+    Payload::Bytes(bytes)
+  }
+}
+
+impl From<Payload<'_>> for Bytes {
+  /// Converts the payload without copying it, unless it is borrowed.
+  fn from(cow: Payload<'_>) -> Self {
+    match cow {
+      Payload::Borrowed(borrowed) => Bytes::copy_from_slice(borrowed),
+      Payload::BorrowedMut(borrowed_mut) => {
+        Bytes::copy_from_slice(borrowed_mut)
+      }
+      Payload::Owned(owned) => Bytes::from(owned),
+      Payload::Bytes(b) => b.freeze(),
+    }
+  }
+}
+
 impl From<Payload<'_>> for Vec<u8> {
   fn from(cow: Payload<'_>) -> Self {
     match cow {
@@ -102,6 +100,7 @@ impl From<Payload<'_>> for Vec<u8> {
 }
 
 impl Payload<'_> {
+  /// Returns the payload as a mutable slice, copying a `Borrowed` payload into an `Owned` one first.
   #[inline(always)]
   pub fn to_mut(&mut self) -> &mut [u8] {
     match self {
@@ -141,8 +140,84 @@ pub struct Frame<'f> {
   mask: Option<[u8; 4]>,
   /// The payload of the frame.
   pub payload: Payload<'f>,
-  /// Is the frame payload compressed
+  /// Is the frame payload compressed. This is the RSV1 bit of the frame.
   pub compressed: bool,
+  /// The RSV2 bit, for custom extensions. Received frames only have it set if
+  /// `WebSocket::set_allow_reserved_bits` is enabled.
+  pub rsv2: bool,
+  /// The RSV3 bit, see `rsv2`.
+  pub rsv3: bool,
+}
+
+/// A frame read from a connection, classified by what it means to the application.
+pub enum Event<'f> {
+  /// A text, binary or continuation frame, or a frame with a reserved opcode.
+  Data(Frame<'f>),
+  /// A ping, with its payload.
+  Ping(Payload<'f>),
+  /// A pong, with its payload.
+  Pong(Payload<'f>),
+  /// The peer's close frame, with its close code and reason. No frame follows it.
+  Close(CloseCode, String),
+}
+
+impl<'f> Event<'f> {
+  /// Classifies a frame, parsing the payload of a close frame.
+  pub(crate) fn from_frame(frame: Frame<'f>) -> Result<Self, WebSocketError> {
+    Ok(match frame.opcode {
+      OpCode::Ping => Event::Ping(frame.payload),
+      OpCode::Pong => Event::Pong(frame.payload),
+      OpCode::Close => {
+        let (code, reason) = crate::CloseFrame::parse(&frame.payload)?;
+        Event::Close(code, reason.to_owned())
+      }
+      _ => Event::Data(frame),
+    })
+  }
+}
+
+/// The parsed header of an incoming WebSocket frame, before its payload is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHeader {
+  /// Indicates if this is the final frame in a message.
+  pub fin: bool,
+  /// The opcode of the frame.
+  pub opcode: OpCode,
+  /// Whether the payload is masked.
+  pub masked: bool,
+  /// Whether the payload is compressed.
+  pub compressed: bool,
+  /// The declared payload length in bytes.
+  pub length: usize,
+}
+
+/// Byte counts of the permessage-deflate traffic of a connection.
+///
+/// Only compressed messages are counted, all fields stay at zero while compression is disabled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+  /// Compressed bytes received.
+  pub compressed_in: u64,
+  /// Bytes received after inflating them.
+  pub decompressed_in: u64,
+  /// Compressed bytes sent.
+  pub compressed_out: u64,
+  /// Bytes sent before compressing them.
+  pub raw_out: u64,
+}
+
+/// Counts of how the frames of a connection were written, to tune `WebSocket::set_writev_threshold`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WriteStats {
+  /// Frames written with a single vectored write of their header and payload.
+  pub vectored_frames: u64,
+  /// Frames copied into the write buffer before being written, including the frames of messages.
+  pub copied_frames: u64,
+  /// Frames written with one write for their header and another for their payload, see
+  /// `WebSocket::set_copy_threshold`.
+  pub split_frames: u64,
+  /// Bytes written, frame headers included.
+  pub bytes_written: u64,
 }
 
 const MAX_HEAD_SIZE: usize = 16;
@@ -162,6 +237,8 @@ impl<'f> Frame<'f> {
       mask,
       payload,
       compressed,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
@@ -177,6 +254,8 @@ impl<'f> Frame<'f> {
       mask: None,
       payload,
       compressed: false,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
@@ -190,6 +269,8 @@ impl<'f> Frame<'f> {
       mask: None,
       payload,
       compressed: false,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
@@ -197,7 +278,8 @@ impl<'f> Frame<'f> {
   ///
   /// This is a convenience method for `Frame::new(true, OpCode::Close, None, payload)`.
   ///
-  /// This method does not check if `code` is a valid close code and `reason` is valid UTF-8.
+  /// This method does not check if `code` is a valid close code and `reason` is valid UTF-8, see
+  /// `Frame::try_close`.
   pub fn close(code: u16, reason: &[u8]) -> Self {
     let mut payload = Vec::with_capacity(2 + reason.len());
     payload.extend_from_slice(&code.to_be_bytes());
@@ -209,9 +291,34 @@ impl<'f> Frame<'f> {
       mask: None,
       payload: payload.into(),
       compressed: false,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
+  /// Create a new WebSocket close `Frame`, checking that it can be sent.
+  ///
+  /// Returns `WebSocketError::InvalidCloseCode` if `code` may not appear in a close frame, such as 1005,
+  /// 1006 and 1015 which are only reported locally, `WebSocketError::InvalidUTF8` if `reason` is not valid
+  /// UTF-8 and `WebSocketError::ControlFrameTooLarge` if it is longer than 123 bytes.
+  pub fn try_close(code: u16, reason: &[u8]) -> Result<Self, WebSocketError> {
+    if !CloseCode::from(code).is_allowed() {
+      return Err(WebSocketError::InvalidCloseCode);
+    }
+    if !validate_utf8(reason) {
+      return Err(WebSocketError::InvalidUTF8);
+    }
+    if reason.len() > 123 {
+      return Err(WebSocketError::ControlFrameTooLarge);
+    }
+    Ok(Self::close(code, reason))
+  }
+
+  /// Create a new WebSocket close `Frame` with no payload, meaning no status code is given.
+  pub fn close_empty() -> Self {
+    Self::close_raw(Payload::Owned(Vec::new()))
+  }
+
   /// Create a new WebSocket close `Frame` with a raw payload.
   ///
   /// This is a convenience method for `Frame::new(true, OpCode::Close, None, payload)`.
@@ -224,6 +331,8 @@ impl<'f> Frame<'f> {
       mask: None,
       payload,
       compressed: false,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
@@ -237,18 +346,57 @@ impl<'f> Frame<'f> {
       mask: None,
       payload,
       compressed: false,
+      rsv2: false,
+      rsv3: false,
     }
   }
 
   /// Checks if the frame payload is valid UTF-8.
   pub fn is_utf8(&self) -> bool {
-    #[cfg(feature = "simd")]
-    return simdutf8::basic::from_utf8(&self.payload).is_ok();
+    validate_utf8(&self.payload)
+  }
+
+  /// Returns the payload of a `Text` frame as a string slice, if it is valid UTF-8.
+  ///
+  /// This is a checked conversion, the same work as `std::str::from_utf8` (or `simdutf8` with that
+  /// feature): it does not skip the validation `read_frame` may have done, because the public `payload`
+  /// field can be changed in between. Continuation frames return `None`, on their own they may end in the
+  /// middle of a character; use `FragmentCollector` to get whole text messages.
+  pub fn as_text(&self) -> Option<&str> {
+    if self.opcode != OpCode::Text {
+      return None;
+    }
+
+    from_utf8(&self.payload)
+  }
 
-    #[cfg(not(feature = "simd"))]
-    return std::str::from_utf8(&self.payload).is_ok();
+  /// Returns the payload as a mutable slice, to transform it in-place.
+  ///
+  /// `Owned`, `BorrowedMut` and `Bytes` payloads are modified where they are. A `Borrowed` payload
+  /// is copied into an `Owned` one first, leaving the borrowed data untouched; the frame keeps its
+  /// lifetime but no longer borrows from it.
+  pub fn payload_mut(&mut self) -> &mut [u8] {
+    self.payload.to_mut()
   }
 
+  /// Replaces the payload of the frame, returning the previous one.
+  ///
+  /// The mask, if any, is kept: `write_frame` applies it to the new payload. Use this to resize the
+  /// payload, `payload_mut` to modify it in-place.
+  pub fn set_payload(&mut self, payload: Payload<'f>) -> Payload<'f> {
+    std::mem::replace(&mut self.payload, payload)
+  }
+
+  /// Returns the masking key of the frame, if any.
+  ///
+  /// Frames sent by a client carry one. `WebSocket::read_frame` unmasks their payload but keeps the key,
+  /// unless auto apply mask is disabled, in which case the payload is still masked with it.
+  #[inline]
+  pub fn mask_key(&self) -> Option<[u8; 4]> {
+    self.mask
+  }
+
+  /// Masks the frame payload in-place, with its masking key or a new random one if it has none.
   pub fn mask(&mut self) {
     if let Some(mask) = self.mask {
       crate::mask::unmask(self.payload.to_mut(), mask);
@@ -268,13 +416,45 @@ impl<'f> Frame<'f> {
     }
   }
 
+  /// Replaces the payload with one that is not masked, dropping the masking key.
+  pub(crate) fn set_unmasked_payload(&mut self, payload: Payload<'f>) {
+    self.payload = payload;
+    self.mask = None;
+  }
+
+  /// Returns the header of the frame, with the length of its current payload.
+  pub(crate) fn header(&self) -> FrameHeader {
+    FrameHeader {
+      fin: self.fin,
+      opcode: self.opcode,
+      masked: self.mask.is_some(),
+      compressed: self.compressed,
+      length: self.payload.len(),
+    }
+  }
+
+  /// Returns the size of the encoded frame, header included.
+  pub(crate) fn encoded_len(&self) -> usize {
+    let len = self.payload.len();
+    let size = match len {
+      0..=125 => 2,
+      126..=65535 => 4,
+      _ => 10,
+    };
+    size + self.mask.map_or(0, |_| 4) + len
+  }
+
   /// Formats the frame header into the head buffer. Returns the size of the length field.
   ///
   /// # Panics
   ///
   /// This method panics if the head buffer is not at least n-bytes long, where n is the size of the length field (0, 2, 4, or 10)
   pub fn fmt_head(&mut self, head: &mut [u8]) -> usize {
-    head[0] = (self.fin as u8) << 7 | (self.opcode as u8);
+    head[0] = (self.fin as u8) << 7
+      | (self.compressed as u8) << 6
+      | (self.rsv2 as u8) << 5
+      | (self.rsv3 as u8) << 4
+      | (u8::from(self.opcode) & 0x0F);
 
     let len = self.payload.len();
     let size = if len < 126 {
@@ -299,6 +479,27 @@ impl<'f> Frame<'f> {
     }
   }
 
+  /// Writes the frame to `stream` with vectored writes: the header and the payload are passed as two
+  /// `IoSlice`s, without copying the payload. This is what `WebSocket::write_frame` uses for large frames.
+  ///
+  /// The frame is written as is. A client must mask it first with `Frame::mask`, which `write_frame` does
+  /// unless auto apply mask is disabled, and a server must not mask it. The payload must already be
+  /// compressed if `compressed` is set.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::Frame;
+  ///
+  /// # async fn run() -> std::io::Result<()> {
+  /// let mut out = Vec::new();
+  /// let mut frame = Frame::binary(b"hello".as_ref().into());
+  /// frame.mask();
+  /// frame.writev(&mut out).await?;
+  /// assert_eq!(out.len(), 2 + 4 + 5);
+  /// # Ok(())
+  /// # }
+  /// ```
   pub async fn writev<S>(
     &mut self,
     stream: &mut S,
@@ -335,6 +536,10 @@ impl<'f> Frame<'f> {
   }
 
   /// Writes the frame to the buffer and returns a slice of the buffer containing the frame.
+  ///
+  /// The buffer is overwritten from its start and grown if it is too small, the bytes after the returned
+  /// slice are left over from before. Like `writev`, the frame is encoded as is, so it must be masked first
+  /// when sent by a client.
   pub fn write<'a>(&mut self, buf: &'a mut Vec<u8>) -> &'a [u8] {
     fn reserve_enough(buf: &mut Vec<u8>, len: usize) {
       if buf.len() < len {
@@ -349,48 +554,326 @@ impl<'f> Frame<'f> {
     &buf[..size + len]
   }
 
-  pub fn inflate(&self, state: &mut InflateState) -> Result<Self, WebSocketError>
-  {
-      let payload = [self.payload.to_vec().as_slice(), &TRAILER].concat();
-
-      let max_output_size = usize::max_value();
-      let mut out: Vec<u8> = vec![0; payload.len().saturating_mul(2).min(max_output_size)];
+  /// Appends the frame to the end of the buffer.
+  pub(crate) fn append_to(&mut self, buf: &mut Vec<u8>) {
+    let start = buf.len();
+    let len = self.payload.len();
+    buf.resize(start + len + MAX_HEAD_SIZE, 0);
 
-      let res = inflate(state, &payload, &mut out, MZFlush::None);
+    let size = self.fmt_head(&mut buf[start..]);
+    buf[start + size..start + size + len].copy_from_slice(&self.payload);
+    buf.truncate(start + size + len);
+  }
 
-      if res.status != Ok(MZStatus::Ok) {
-        return Err(WebSocketError::InvalidEncoding);
-      }
+  /// Inflates a compressed frame payload using the provided inflate state.
+  ///
+  /// The output buffer is grown until the whole payload has been consumed and
+  /// all pending output has been drained. Returns `WebSocketError::FrameTooLarge`
+  /// if the inflated payload exceeds `max_size` bytes.
+  ///
+  /// The frames of a fragmented message continue the same deflate stream, they
+  /// must be inflated in order with the same state. The stream is only
+  /// terminated on the final frame.
+  pub fn inflate(
+    &self,
+    state: &mut InflateState,
+    max_size: usize,
+  ) -> Result<Self, WebSocketError> {
+    let mut out = Vec::new();
+    Inflate::inflate(state, &self.payload, self.fin, max_size, &mut out)?;
+    let payload = Payload::Owned(out);
+
+    Ok(Self {
+      fin: self.fin,
+      opcode: self.opcode,
+      mask: self.mask,
+      payload,
+      compressed: false,
+      rsv2: self.rsv2,
+      rsv3: self.rsv3,
+    })
+  }
+}
 
-      out.truncate(res.bytes_written);
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpCode {
+  Continuation,
+  Text,
+  Binary,
+  Close,
+  Ping,
+  Pong,
+  /// A reserved opcode, from 0x3 to 0x7 for data frames and from 0xB to 0xF for control frames. Frames
+  /// with one are only returned with `UnknownOpcode::Deliver`, and can be written back as they are: writing
+  /// one holding a standard or out of range value fails with `WebSocketError::InvalidValue`.
+  Other(u8),
+}
 
-      let payload = Payload::Owned(out);
+impl OpCode {
+  /// Returns whether this is a control opcode, from 0x8 to 0xF. Reserved opcodes are classified by their
+  /// value too.
+  #[inline]
+  pub fn is_control(self) -> bool {
+    u8::from(self) & 0x08 != 0
+  }
 
-      Ok(Self {
-        fin: self.fin,
-        opcode: self.opcode,
-        mask: self.mask,
-        payload,
-        compressed: false
-      })
+  /// Maps the opcode bits of a frame header, reserved values included.
+  pub(crate) fn from_bits(bits: u8) -> Self {
+    match bits & 0x0F {
+      0x0 => OpCode::Continuation,
+      0x1 => OpCode::Text,
+      0x2 => OpCode::Binary,
+      0x8 => OpCode::Close,
+      0x9 => OpCode::Ping,
+      0xA => OpCode::Pong,
+      other => OpCode::Other(other),
+    }
   }
+}
 
+impl From<OpCode> for u8 {
+  fn from(opcode: OpCode) -> u8 {
+    match opcode {
+      OpCode::Continuation => 0x0,
+      OpCode::Text => 0x1,
+      OpCode::Binary => 0x2,
+      OpCode::Close => 0x8,
+      OpCode::Ping => 0x9,
+      OpCode::Pong => 0xA,
+      OpCode::Other(value) => value,
+    }
+  }
 }
 
-repr_u8! {
-    #[repr(u8)]
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    pub enum OpCode {
-        Continuation = 0x0,
-        Text = 0x1,
-        Binary = 0x2,
-        Close = 0x8,
-        Ping = 0x9,
-        Pong = 0xA,
+/// Only the opcodes defined by RFC 6455 are accepted, reserved values fail with
+/// `WebSocketError::InvalidValue`.
+impl TryFrom<u8> for OpCode {
+  type Error = WebSocketError;
+
+  fn try_from(v: u8) -> Result<Self, Self::Error> {
+    match OpCode::from_bits(v) {
+      OpCode::Other(_) => Err(WebSocketError::InvalidValue),
+      _ if v > 0x0F => Err(WebSocketError::InvalidValue),
+      opcode => Ok(opcode),
     }
+  }
+}
+
+/// What to do with frames using a reserved opcode, see `WebSocket::set_on_unknown_opcode`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnknownOpcode {
+  /// Fail the read with `WebSocketError::InvalidValue`.
+  Error,
+  /// Read the frame and discard it, along with the continuation frames of its message.
+  IgnoreSkip,
+  /// Return the frame with its opcode in `OpCode::Other`.
+  Deliver,
 }
 
 #[inline]
 pub fn is_control(opcode: OpCode) -> bool {
-  matches!(opcode, OpCode::Close | OpCode::Ping | OpCode::Pong)
+  opcode.is_control()
+}
+
+/// Checks that `bytes` is valid UTF-8.
+#[inline]
+pub(crate) fn validate_utf8(bytes: &[u8]) -> bool {
+  from_utf8(bytes).is_some()
+}
+
+/// Validates `bytes` as UTF-8, using `simdutf8` when the feature is enabled.
+///
+/// Every whole-payload UTF-8 check goes through here, and incremental ones through `check_utf8`, so the
+/// choice of validator lives in one place.
+#[inline]
+pub(crate) fn from_utf8(bytes: &[u8]) -> Option<&str> {
+  #[cfg(feature = "simdutf8")]
+  return simdutf8::basic::from_utf8(bytes).ok();
+
+  #[cfg(not(feature = "simdutf8"))]
+  return std::str::from_utf8(bytes).ok();
+}
+
+/// Where `check_utf8` found invalid UTF-8, as in `std::str::Utf8Error`.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Utf8Error {
+  /// The length of the valid prefix.
+  pub valid_up_to: usize,
+  /// The length of the invalid sequence, `None` if the input ends in the middle of a character.
+  pub error_len: Option<usize>,
+}
+
+/// Validates `bytes` as UTF-8 like `from_utf8`, reporting where it failed so that input cut in the middle
+/// of a character can be completed by the next bytes.
+#[inline]
+pub(crate) fn check_utf8(bytes: &[u8]) -> Result<(), Utf8Error> {
+  #[cfg(feature = "simdutf8")]
+  let res = simdutf8::compat::from_utf8(bytes).map_err(|e| Utf8Error {
+    valid_up_to: e.valid_up_to(),
+    error_len: e.error_len(),
+  });
+
+  #[cfg(not(feature = "simdutf8"))]
+  let res = std::str::from_utf8(bytes).map_err(|e| Utf8Error {
+    valid_up_to: e.valid_up_to(),
+    error_len: e.error_len(),
+  });
+
+  res.map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use miniz_oxide::DataFormat;
+
+  #[test]
+  fn try_close() {
+    for code in [1005, 1006, 1015, 1004, 999, 2000, 5000] {
+      assert!(matches!(
+        Frame::try_close(code, b""),
+        Err(WebSocketError::InvalidCloseCode)
+      ));
+    }
+    assert!(matches!(
+      Frame::try_close(1000, b"\xff"),
+      Err(WebSocketError::InvalidUTF8)
+    ));
+    assert!(matches!(
+      Frame::try_close(1000, &[b'a'; 124]),
+      Err(WebSocketError::ControlFrameTooLarge)
+    ));
+
+    let frame = Frame::try_close(4000, b"bye").unwrap();
+    assert_eq!(&frame.payload[..], b"\x0f\xa0bye");
+  }
+
+  #[test]
+  fn mask_key() {
+    let mut frame = Frame::text(b"abcd".to_vec().into());
+    assert_eq!(frame.mask_key(), None);
+    frame.mask();
+    let key = frame.mask_key().unwrap();
+    assert_eq!(
+      &frame.payload[..],
+      [b'a' ^ key[0], b'b' ^ key[1], b'c' ^ key[2], b'd' ^ key[3]]
+    );
+    frame.unmask();
+    assert_eq!(&frame.payload[..], b"abcd");
+    assert_eq!(frame.mask_key(), Some(key));
+  }
+
+  #[test]
+  fn opcode() {
+    for value in 0..=0x0F {
+      let opcode = OpCode::from_bits(value);
+      assert_eq!(u8::from(opcode), value);
+      assert_eq!(opcode.is_control(), value >= 0x08);
+      assert_eq!(
+        OpCode::try_from(value).is_ok(),
+        !matches!(opcode, OpCode::Other(_))
+      );
+    }
+    assert!(OpCode::try_from(0x11).is_err());
+
+    let mut buf = Vec::new();
+    let mut frame =
+      Frame::new(true, OpCode::Other(0xB), None, b"".as_ref().into(), false);
+    assert_eq!(frame.write(&mut buf), [0x8B, 0]);
+  }
+
+  #[test]
+  fn utf8() {
+    assert_eq!(from_utf8("héllo".as_bytes()), Some("héllo"));
+    assert_eq!(from_utf8(b""), Some(""));
+    assert!(!validate_utf8(b"\xff"));
+    // Truncated in the middle of a character.
+    assert!(!validate_utf8(&"é".as_bytes()[..1]));
+
+    assert_eq!(check_utf8("héllo".as_bytes()), Ok(()));
+    let cut = Utf8Error {
+      valid_up_to: 1,
+      error_len: None,
+    };
+    assert_eq!(check_utf8(&"hé".as_bytes()[..2]), Err(cut));
+    let invalid = Utf8Error {
+      valid_up_to: 1,
+      error_len: Some(1),
+    };
+    assert_eq!(check_utf8(b"h\xffllo"), Err(invalid));
+  }
+
+  // Compresses `data` the way a permessage-deflate peer would.
+  fn compress(data: &[u8]) -> Vec<u8> {
+    crate::deflate::new_deflater(6).deflate(data, true).unwrap()
+  }
+
+  #[test]
+  fn payload_mut() {
+    let data = b"hello".to_vec();
+    let mut frame = Frame::binary(data.as_slice().into());
+    frame.payload_mut()[0] = b'j';
+    assert!(matches!(frame.payload, Payload::Owned(_)));
+    assert_eq!(&frame.payload[..], b"jello");
+    assert_eq!(data, b"hello");
+
+    let mut data = b"hello".to_vec();
+    let mut frame = Frame::binary(data.as_mut_slice().into());
+    frame.payload_mut()[0] = b'j';
+    assert!(matches!(frame.payload, Payload::BorrowedMut(_)));
+    drop(frame);
+    assert_eq!(data, b"jello");
+  }
+
+  #[test]
+  fn set_payload() {
+    let mut frame = Frame::text(b"hello".as_ref().into());
+    let old = frame.set_payload(b"goodbye".to_vec().into());
+    assert_eq!(&old[..], b"hello");
+    assert_eq!(&frame.payload[..], b"goodbye");
+  }
+
+  #[test]
+  fn as_text() {
+    let frame = Frame::text(b"hello".as_ref().into());
+    assert_eq!(frame.as_text(), Some("hello"));
+
+    let frame = Frame::text(b"\xff".as_ref().into());
+    assert_eq!(frame.as_text(), None);
+
+    let frame = Frame::binary(b"hello".as_ref().into());
+    assert_eq!(frame.as_text(), None);
+  }
+
+  #[test]
+  fn inflate_high_compression_ratio() {
+    let data = b"fastwebsockets".repeat(1024 * 1024 / 14);
+    let compressed = compress(&data);
+    assert!(compressed.len() < 8 * 1024);
+
+    let frame =
+      Frame::new(true, OpCode::Binary, None, Payload::Owned(compressed), true);
+    let mut state = InflateState::new(DataFormat::Raw);
+    let inflated = frame.inflate(&mut state, 64 << 20).unwrap();
+    assert!(!inflated.compressed);
+    assert_eq!(&inflated.payload[..], &data[..]);
+  }
+
+  #[test]
+  fn inflate_exceeds_max_size() {
+    let data = vec![0; 1024 * 1024];
+    let frame = Frame::new(
+      true,
+      OpCode::Binary,
+      None,
+      Payload::Owned(compress(&data)),
+      true,
+    );
+    let mut state = InflateState::new(DataFormat::Raw);
+    assert!(matches!(
+      frame.inflate(&mut state, 1024),
+      Err(WebSocketError::FrameTooLarge)
+    ));
+  }
 }
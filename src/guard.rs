@@ -0,0 +1,135 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::pin::Pin;
+use std::task::Context;
+
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+use crate::CloseCode;
+use crate::Frame;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Sends a close frame when a `WebSocket` is dropped without having been closed.
+///
+/// Without it, a dropped connection is only seen by the peer as the stream ending, which it reports as
+/// abnormal closure (1006). `Drop` can not wait for the write, so the close frame is written with a single
+/// attempt that does not block: it goes out if the stream accepts it right away, which is the common case
+/// for a socket that is not congested, and is lost otherwise. Call `close` to send it reliably.
+///
+/// The guard derefs to the `WebSocket`, and does nothing if a close frame was already sent.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{CloseCode, CloseGuard, WebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn handle(ws: WebSocket<TcpStream>) -> Result<()> {
+///   let mut ws = CloseGuard::new(ws, CloseCode::Away);
+///   let frame = ws.read_frame().await?;
+///   // Returning early with `?` still sends a close frame.
+///   ws.close().await?;
+///   Ok(())
+/// }
+/// ```
+pub struct CloseGuard<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  ws: Option<WebSocket<S>>,
+  code: CloseCode,
+}
+
+impl<S> CloseGuard<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  /// Wraps `ws`, sending a close frame with `code` when the guard is dropped.
+  pub fn new(ws: WebSocket<S>, code: CloseCode) -> Self {
+    Self { ws: Some(ws), code }
+  }
+
+  /// Changes the close code sent on drop.
+  pub fn set_code(&mut self, code: CloseCode) {
+    self.code = code;
+  }
+
+  /// Sends the close frame and flushes it, waiting for the write. Nothing is sent on drop afterwards.
+  pub async fn close(mut self) -> Result<WebSocket<S>, WebSocketError> {
+    let mut ws = self.ws.take().unwrap();
+    if !ws.write_half.closed {
+      let frame = Frame::close(self.code.into(), &[]);
+      ws.write_half.write_frame(&mut ws.stream, frame).await?;
+      ws.stream.flush().await?;
+    }
+    Ok(ws)
+  }
+
+  /// Returns the `WebSocket` without sending a close frame.
+  pub fn into_inner(mut self) -> WebSocket<S> {
+    self.ws.take().unwrap()
+  }
+}
+
+impl<S> Deref for CloseGuard<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  type Target = WebSocket<S>;
+
+  fn deref(&self) -> &Self::Target {
+    self.ws.as_ref().unwrap()
+  }
+}
+
+impl<S> DerefMut for CloseGuard<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    self.ws.as_mut().unwrap()
+  }
+}
+
+impl<S> Drop for CloseGuard<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  fn drop(&mut self) {
+    let Some(ws) = self.ws.as_mut() else {
+      return;
+    };
+    if ws.write_half.closed {
+      return;
+    }
+
+    let mut buf = Vec::new();
+    let frame = Frame::close(self.code.into(), &[]);
+    if ws.write_half.encode_frame(frame, &mut buf).is_err() {
+      return;
+    }
+    // A single attempt, a pending or partial write is given up on since the
+    // stream is dropped right after.
+    let mut cx = Context::from_waker(std::task::Waker::noop());
+    let mut stream = Pin::new(&mut ws.stream);
+    let _ = stream.as_mut().poll_write(&mut cx, &buf);
+    let _ = stream.poll_flush(&mut cx);
+  }
+}
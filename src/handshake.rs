@@ -12,30 +12,56 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "upgrade")]
 use hyper::body::Incoming;
+#[cfg(feature = "upgrade")]
 use hyper::upgrade::Upgraded;
+#[cfg(feature = "upgrade")]
 use hyper::Request;
+#[cfg(feature = "upgrade")]
 use hyper::Response;
+#[cfg(feature = "upgrade")]
 use hyper::StatusCode;
 
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use sha1::Digest;
+use sha1::Sha1;
 
+#[cfg(feature = "upgrade")]
 use hyper_util::rt::TokioIo;
 use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
 
+#[cfg(feature = "upgrade")]
 use std::future::Future;
+#[cfg(feature = "upgrade")]
 use std::pin::Pin;
 
+use crate::ExtensionConfig;
 use crate::Role;
 use crate::WebSocket;
 use crate::WebSocketError;
 
+/// Upper bound on the size of the HTTP head read by [`server`] and [`client_raw`].
+const MAX_HEAD_SIZE: usize = 8192;
+/// Upper bound on the number of headers read by [`server`] and [`client_raw`].
+const MAX_HEADERS: usize = 64;
+/// Headers of the upgrade request that callers of [`client_raw`] cannot set.
+const RESERVED_HEADERS: [&str; 4] =
+  ["Host", "Upgrade", "Connection", "Sec-WebSocket-Version"];
+
 /// Perform the client handshake.
 ///
 /// This function is used to perform the client handshake. It takes a hyper
-/// executor, a `hyper::Request` and a stream.
+/// executor, a `hyper::Request` and a stream, and returns the websocket along
+/// with the [`HandshakeResponse`] of the server.
+///
+/// permessage-deflate is set up if the server accepted it. Selecting an
+/// extension that was not offered in a `Sec-WebSocket-Extensions` header of
+/// the request fails with `WebSocketError::InvalidExtension`.
 ///
 /// # Example
 ///
@@ -82,11 +108,13 @@ use crate::WebSocketError;
 ///   }
 /// }
 /// ```
+#[cfg(feature = "upgrade")]
+#[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
 pub async fn client<S, E, B>(
   executor: &E,
   request: Request<B>,
   socket: S,
-) -> Result<(WebSocket<TokioIo<Upgraded>>, Response<Incoming>), WebSocketError>
+) -> Result<(WebSocket<TokioIo<Upgraded>>, HandshakeResponse), WebSocketError>
 where
   S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
   E: hyper::rt::Executor<Pin<Box<dyn Future<Output = ()> + Send>>>,
@@ -103,27 +131,467 @@ where
   });
   executor.execute(fut);
 
+  let offers: Vec<Vec<u8>> = request
+    .headers()
+    .get_all("Sec-WebSocket-Extensions")
+    .iter()
+    .map(|value| value.as_bytes().to_vec())
+    .collect();
   let mut response = sender.send_request(request).await?;
   verify(&response)?;
 
+  let headers = response
+    .headers()
+    .iter()
+    .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+    .collect();
+  let handshake = HandshakeResponse::new(response.status().as_u16(), headers)?;
+  check_extensions(
+    offers.iter().map(Vec::as_slice),
+    &handshake.selected_extensions(),
+  )?;
+
+  // Bytes hyper read past the response are kept in `Upgraded` and returned
+  // by its first reads, so frames sent right after the 101 are not lost.
   match hyper::upgrade::on(&mut response).await {
-    Ok(upgraded) => Ok((
-      WebSocket::after_handshake(TokioIo::new(upgraded), Role::Client),
-      response,
-    )),
+    Ok(upgraded) => {
+      let mut ws =
+        WebSocket::after_handshake(TokioIo::new(upgraded), Role::Client);
+      ws.set_extensions(handshake.selected_extensions());
+      ws.protocol = handshake.selected_protocol().map(str::to_string);
+      Ok((ws, handshake))
+    }
     Err(e) => Err(e.into()),
   }
 }
 
+/// Perform the server handshake over a stream speaking HTTP/1.1, without hyper.
+///
+/// This function reads the upgrade request from the stream, validates it and
+/// writes the `101 Switching Protocols` response. Bytes the client sent after
+/// the request are kept for the first frame read.
+///
+/// If the request is not a valid websocket upgrade request, an error is
+/// returned and nothing is written; it is up to the caller to reply and close
+/// the stream.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::handshake;
+/// use fastwebsockets::WebSocket;
+/// use tokio::net::TcpListener;
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn accept(listener: &TcpListener) -> Result<WebSocket<TcpStream>> {
+///   let (stream, _) = listener.accept().await?;
+///   let ws = handshake::server(stream).await?;
+///   Ok(ws)
+/// }
+/// ```
+pub async fn server<S>(mut stream: S) -> Result<WebSocket<S>, WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let mut buf = Vec::with_capacity(1024);
+  let (request_len, accept) = read_head(&mut stream, &mut buf, |buf| {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut request = httparse::Request::new(&mut headers);
+    match request.parse(buf) {
+      Ok(httparse::Status::Complete(len)) => {
+        Ok(Some((len, verify_request(&request)?)))
+      }
+      Ok(httparse::Status::Partial) if buf.len() < MAX_HEAD_SIZE => Ok(None),
+      _ => Err(WebSocketError::InvalidUpgradeRequest),
+    }
+  })
+  .await?;
+
+  let response = format!(
+    "HTTP/1.1 101 Switching Protocols\r\n\
+     Upgrade: websocket\r\n\
+     Connection: Upgrade\r\n\
+     Sec-WebSocket-Accept: {accept}\r\n\r\n"
+  );
+  stream.write_all(response.as_bytes()).await?;
+  stream.flush().await?;
+
+  let mut ws = WebSocket::after_handshake(stream, Role::Server);
+  ws.read_half.buffer.extend_from_slice(&buf[request_len..]);
+  Ok(ws)
+}
+
+/// The response of the server to a client handshake.
+#[derive(Debug, Clone)]
+pub struct HandshakeResponse {
+  status: u16,
+  headers: Vec<(String, Vec<u8>)>,
+  extensions: ExtensionConfig,
+}
+
+impl HandshakeResponse {
+  /// Parses the extensions negotiated in the `Sec-WebSocket-Extensions`
+  /// headers, which may be repeated.
+  fn new(
+    status: u16,
+    headers: Vec<(String, Vec<u8>)>,
+  ) -> Result<Self, WebSocketError> {
+    let mut extensions = Vec::new();
+    for (name, value) in &headers {
+      if name.eq_ignore_ascii_case("Sec-WebSocket-Extensions") {
+        extensions.push(
+          std::str::from_utf8(value)
+            .map_err(|_| WebSocketError::InvalidExtension)?,
+        );
+      }
+    }
+    let extensions = ExtensionConfig::parse(&extensions.join(","))?;
+
+    Ok(Self {
+      status,
+      headers,
+      extensions,
+    })
+  }
+
+  /// Returns the status code of the response.
+  pub fn status(&self) -> u16 {
+    self.status
+  }
+
+  /// Returns the value of the first header named `name`, ignoring case.
+  pub fn header(&self, name: &str) -> Option<&[u8]> {
+    self
+      .headers()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|h| h.1)
+  }
+
+  /// Returns the headers of the response, in order.
+  pub fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+    self
+      .headers
+      .iter()
+      .map(|(name, value)| (name.as_str(), value.as_slice()))
+  }
+
+  /// Returns the subprotocol selected by the server in the
+  /// `Sec-WebSocket-Protocol` header.
+  ///
+  /// Returns `None` if the header is missing, empty or not valid UTF-8, and
+  /// if the server sent more than one value, as it must select a single one.
+  pub fn selected_protocol(&self) -> Option<&str> {
+    let mut protocols = self
+      .headers()
+      .filter(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Protocol"))
+      .flat_map(|(_, value)| value.split(|&c| c == b','))
+      .map(|value| value.trim_ascii())
+      .filter(|value| !value.is_empty());
+
+    match (protocols.next(), protocols.next()) {
+      (Some(protocol), None) => std::str::from_utf8(protocol).ok(),
+      _ => None,
+    }
+  }
+
+  /// Returns the extensions negotiated by the server, as applied to the
+  /// connection.
+  pub fn selected_extensions(&self) -> ExtensionConfig {
+    self.extensions
+  }
+}
+
+/// Perform the client handshake over a stream, without hyper.
+///
+/// This function writes the upgrade request for `path` to the stream, with a
+/// `Host` header set to `host` followed by `headers`, and reads the response of
+/// the server. The `Sec-WebSocket-Accept` header of the response is checked
+/// against the key that was sent, and permessage-deflate is set up if the
+/// server accepted it. Selecting an extension that was not offered in a
+/// `Sec-WebSocket-Extensions` header fails with
+/// `WebSocketError::InvalidExtension`.
+///
+/// The key is generated with [`generate_key`], unless `headers` contains a
+/// `Sec-WebSocket-Key`, for example from [`generate_key_from`]. The other
+/// headers written by the handshake (`Host`, `Upgrade`, `Connection` and
+/// `Sec-WebSocket-Version`) cannot be set, and are rejected with
+/// `WebSocketError::ReservedHeader`. Line breaks in `path`, `host` or a header,
+/// and spaces in `path`, are rejected with `WebSocketError::InvalidValue`.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::handshake;
+/// use fastwebsockets::WebSocket;
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn connect() -> Result<WebSocket<TcpStream>> {
+///   let stream = TcpStream::connect("localhost:9001").await?;
+///   let (ws, _) = handshake::client_raw(
+///     stream,
+///     "/",
+///     "localhost:9001",
+///     &[
+///       ("Origin", "https://example.com"),
+///       ("Authorization", "Bearer token"),
+///       ("Sec-WebSocket-Protocol", "chat"),
+///     ],
+///   )
+///   .await?;
+///   Ok(ws)
+/// }
+/// ```
+pub async fn client_raw<S>(
+  mut stream: S,
+  path: &str,
+  host: &str,
+  headers: &[(&str, &str)],
+) -> Result<(WebSocket<S>, HandshakeResponse), WebSocketError>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let key = headers
+    .iter()
+    .find(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Key"))
+    .map_or_else(generate_key, |(_, key)| key.to_string());
+  // Line breaks would allow injecting headers or splitting the request, and a
+  // space would end the request target.
+  if [&key, host].iter().any(|s| s.contains(['\r', '\n']))
+    || path.contains(['\r', '\n', ' '])
+  {
+    return Err(WebSocketError::InvalidValue);
+  }
+  let mut request = format!(
+    "GET {path} HTTP/1.1\r\n\
+     Host: {host}\r\n\
+     Upgrade: websocket\r\n\
+     Connection: Upgrade\r\n\
+     Sec-WebSocket-Key: {key}\r\n\
+     Sec-WebSocket-Version: 13\r\n"
+  );
+  for (name, value) in headers {
+    if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+      continue;
+    }
+    if RESERVED_HEADERS
+      .iter()
+      .any(|h| h.eq_ignore_ascii_case(name))
+    {
+      return Err(WebSocketError::ReservedHeader(name.to_string()));
+    }
+    // Line breaks would allow injecting headers or splitting the request.
+    if [name, value].iter().any(|s| s.contains(['\r', '\n'])) {
+      return Err(WebSocketError::InvalidValue);
+    }
+    request.push_str(&format!("{name}: {value}\r\n"));
+  }
+  request.push_str("\r\n");
+  stream.write_all(request.as_bytes()).await?;
+  stream.flush().await?;
+
+  let accept = accept_key(key.as_bytes());
+  let mut buf = Vec::with_capacity(1024);
+  let (response_len, response) = read_head(&mut stream, &mut buf, |buf| {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+    match response.parse(buf) {
+      Ok(httparse::Status::Complete(len)) => {
+        verify_response(&response, &accept)?;
+        let headers = response
+          .headers
+          .iter()
+          .map(|h| (h.name.to_string(), h.value.to_vec()))
+          .collect();
+        let status = response.code.unwrap_or_default();
+        Ok(Some((len, HandshakeResponse::new(status, headers)?)))
+      }
+      Ok(httparse::Status::Partial) if buf.len() < MAX_HEAD_SIZE => Ok(None),
+      _ => Err(WebSocketError::InvalidUpgradeResponse),
+    }
+  })
+  .await?;
+  let offers = headers
+    .iter()
+    .filter(|(name, _)| name.eq_ignore_ascii_case("Sec-WebSocket-Extensions"))
+    .map(|(_, value)| value.as_bytes());
+  check_extensions(offers, &response.selected_extensions())?;
+
+  let mut ws = WebSocket::after_handshake(stream, Role::Client);
+  ws.set_extensions(response.selected_extensions());
+  ws.protocol = response.selected_protocol().map(str::to_string);
+  ws.read_half.buffer.extend_from_slice(&buf[response_len..]);
+  Ok((ws, response))
+}
+
+/// Reads from `stream` until `parse` finds a complete HTTP head in the bytes
+/// read so far, and returns what it parsed.
+async fn read_head<S, T>(
+  stream: &mut S,
+  buf: &mut Vec<u8>,
+  mut parse: impl FnMut(&[u8]) -> Result<Option<T>, WebSocketError>,
+) -> Result<T, WebSocketError>
+where
+  S: AsyncRead + Unpin,
+{
+  loop {
+    if stream.read_buf(buf).await? == 0 {
+      return Err(WebSocketError::UnexpectedEOF);
+    }
+    if let Some(head) = parse(buf)? {
+      return Ok(head);
+    }
+  }
+}
+
+/// Checks the headers of an upgrade request and returns the value of the
+/// `Sec-WebSocket-Accept` response header.
+fn verify_request(
+  request: &httparse::Request<'_, '_>,
+) -> Result<String, WebSocketError> {
+  if request.method != Some("GET") {
+    return Err(WebSocketError::InvalidUpgradeRequest);
+  }
+  let headers = &*request.headers;
+  if !header_contains_token(headers, "Upgrade", "websocket") {
+    return Err(WebSocketError::InvalidUpgradeHeader);
+  }
+  if !header_contains_token(headers, "Connection", "Upgrade") {
+    return Err(WebSocketError::InvalidConnectionHeader);
+  }
+  if header(headers, "Sec-WebSocket-Version") != Some(b"13") {
+    return Err(WebSocketError::InvalidSecWebsocketVersion);
+  }
+
+  let key = header(headers, "Sec-WebSocket-Key")
+    .ok_or(WebSocketError::MissingSecWebSocketKey)?;
+  Ok(accept_key(key))
+}
+
+/// Checks the status and headers of a handshake response. `accept` is the
+/// expected value of the `Sec-WebSocket-Accept` header.
+fn verify_response(
+  response: &httparse::Response<'_, '_>,
+  accept: &str,
+) -> Result<(), WebSocketError> {
+  let status = response.code.unwrap_or_default();
+  if status != 101 {
+    return Err(WebSocketError::InvalidStatusCode(status));
+  }
+
+  let headers = &*response.headers;
+  if !header_contains_token(headers, "Upgrade", "websocket") {
+    return Err(WebSocketError::InvalidUpgradeHeader);
+  }
+  if !header_contains_token(headers, "Connection", "Upgrade") {
+    return Err(WebSocketError::InvalidConnectionHeader);
+  }
+  if header(headers, "Sec-WebSocket-Accept") != Some(accept.as_bytes()) {
+    return Err(WebSocketError::InvalidSecWebSocketAccept);
+  }
+  Ok(())
+}
+
+fn header<'b>(
+  headers: &[httparse::Header<'b>],
+  name: &str,
+) -> Option<&'b [u8]> {
+  headers
+    .iter()
+    .find(|header| header.name.eq_ignore_ascii_case(name))
+    .map(|header| header.value)
+}
+
+/// Checks if any header named `name` lists `token` in its comma separated value.
+fn header_contains_token(
+  headers: &[httparse::Header<'_>],
+  name: &str,
+  token: &str,
+) -> bool {
+  headers
+    .iter()
+    .filter(|header| header.name.eq_ignore_ascii_case(name))
+    .flat_map(|header| header.value.split(|&c| c == b','))
+    .any(|value| value.trim_ascii().eq_ignore_ascii_case(token.as_bytes()))
+}
+
+/// The GUID appended to the `Sec-WebSocket-Key` to compute the accept key (RFC 6455).
+pub const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Checks the extensions selected by the server against the
+/// `Sec-WebSocket-Extensions` headers of the request. The connection fails if
+/// the server selected an extension the client did not offer (RFC 6455,
+/// section 9.1), or a `client_max_window_bits` below 15, as messages are always
+/// compressed with a 15 bit window.
+fn check_extensions<'a>(
+  offers: impl IntoIterator<Item = &'a [u8]>,
+  selected: &ExtensionConfig,
+) -> Result<(), WebSocketError> {
+  if !selected.permessage_deflate {
+    return Ok(());
+  }
+  let name = if selected.deflate_frame {
+    "x-webkit-deflate-frame"
+  } else {
+    "permessage-deflate"
+  };
+  let offered = offers
+    .into_iter()
+    .flat_map(|value| value.split(|&c| c == b','))
+    .filter_map(|offer| offer.split(|&c| c == b';').next())
+    .any(|offer| offer.trim_ascii().eq_ignore_ascii_case(name.as_bytes()));
+  if !offered || selected.client_max_window_bits < 15 {
+    return Err(WebSocketError::InvalidExtension);
+  }
+  Ok(())
+}
+
+/// Computes the `Sec-WebSocket-Accept` header value for a `Sec-WebSocket-Key`,
+/// `base64(sha1(key + GUID))`. This is what the handshakes of this crate use,
+/// for custom handshakes over other HTTP implementations.
+///
+/// ```
+/// use fastwebsockets::handshake::accept_key;
+///
+/// assert_eq!(
+///   accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+///   "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=",
+/// );
+/// ```
+pub fn accept_key(sec_websocket_key: impl AsRef<[u8]>) -> String {
+  accept_key_with_guid(sec_websocket_key, WEBSOCKET_GUID)
+}
+
+/// Computes the `Sec-WebSocket-Accept` header value for a `Sec-WebSocket-Key`
+/// with another GUID than the one of RFC 6455, for tests or non-standard
+/// peers. The handshakes of this crate always use `accept_key`.
+pub fn accept_key_with_guid(
+  sec_websocket_key: impl AsRef<[u8]>,
+  guid: impl AsRef<[u8]>,
+) -> String {
+  let mut sha1 = Sha1::new();
+  sha1.update(sec_websocket_key);
+  sha1.update(guid);
+  let result = sha1.finalize();
+  STANDARD.encode(&result[..])
+}
+
 /// Generate a random key for the `Sec-WebSocket-Key` header.
 pub fn generate_key() -> String {
+  generate_key_from(rand::random())
+}
+
+/// Generate the `Sec-WebSocket-Key` header value for the given nonce, for
+/// reproducible handshakes or to use another source of randomness.
+pub fn generate_key_from(bytes: [u8; 16]) -> String {
   // a base64-encoded (see Section 4 of [RFC4648]) value that,
   // when decoded, is 16 bytes in length (RFC 6455)
-  let r: [u8; 16] = rand::random();
-  STANDARD.encode(r)
+  STANDARD.encode(bytes)
 }
 
 // https://github.com/snapview/tungstenite-rs/blob/314feea3055a93e585882fb769854a912a7e6dae/src/handshake/client.rs#L189
+#[cfg(feature = "upgrade")]
 fn verify(response: &Response<Incoming>) -> Result<(), WebSocketError> {
   if response.status() != StatusCode::SWITCHING_PROTOCOLS {
     return Err(WebSocketError::InvalidStatusCode(
@@ -153,3 +621,302 @@ fn verify(response: &Response<Incoming>) -> Result<(), WebSocketError> {
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::Frame;
+  use crate::OpCode;
+
+  #[tokio::test]
+  async fn server_handshake() {
+    let (mut client, stream) = tokio::io::duplex(1024);
+    let server = tokio::spawn(server(stream));
+
+    // A masked "Hi" text frame follows the request.
+    client
+      .write_all(
+        b"GET /chat HTTP/1.1\r\n\
+          Host: example.com\r\n\
+          Upgrade: websocket\r\n\
+          Connection: keep-alive, Upgrade\r\n\
+          Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+          Sec-WebSocket-Version: 13\r\n\r\n\
+          \x81\x82\x00\x00\x00\x00Hi",
+      )
+      .await
+      .unwrap();
+
+    let mut ws = server.await.unwrap().unwrap();
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Text);
+    assert_eq!(&frame.payload[..], b"Hi");
+
+    ws.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let mut response = [0; 1024];
+    let n = client.read(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response[..n]);
+    assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(response
+      .contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+  }
+
+  #[cfg(feature = "upgrade")]
+  #[tokio::test]
+  async fn client_frame_after_response() {
+    struct SpawnExecutor;
+
+    impl<Fut> hyper::rt::Executor<Fut> for SpawnExecutor
+    where
+      Fut: Future + Send + 'static,
+      Fut::Output: Send + 'static,
+    {
+      fn execute(&self, fut: Fut) {
+        tokio::task::spawn(fut);
+      }
+    }
+
+    let (stream, mut peer) = tokio::io::duplex(1024);
+    let server = tokio::spawn(async move {
+      let mut request = Vec::new();
+      while !request.ends_with(b"\r\n\r\n") {
+        let mut buf = [0; 1024];
+        let n = peer.read(&mut buf).await.unwrap();
+        assert_ne!(n, 0);
+        request.extend_from_slice(&buf[..n]);
+      }
+      // The first frame is written along with the response, so hyper reads
+      // it before the protocol switch.
+      peer
+        .write_all(
+          b"HTTP/1.1 101 Switching Protocols\r\n\
+            Upgrade: websocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n\
+            \x81\x02Hi",
+        )
+        .await
+        .unwrap();
+      peer
+    });
+
+    let request = Request::builder()
+      .uri("http://example.com/")
+      .header("Host", "example.com")
+      .header("Upgrade", "websocket")
+      .header("Connection", "Upgrade")
+      .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+      .header("Sec-WebSocket-Version", "13")
+      .body(http_body_util::Empty::<hyper::body::Bytes>::new())
+      .unwrap();
+    let (mut ws, _) = client(&SpawnExecutor, request, stream).await.unwrap();
+    let _peer = server.await.unwrap();
+
+    let frame = ws.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Text);
+    assert_eq!(&frame.payload[..], b"Hi");
+  }
+
+  #[tokio::test]
+  async fn client_raw_handshake() {
+    let (stream, peer) = tokio::io::duplex(1024);
+    let server = tokio::spawn(server(peer));
+
+    let (mut client, response) =
+      client_raw(stream, "/chat", "example.com", &[("Origin", "null")])
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 101);
+    assert_eq!(response.header("upgrade"), Some(b"websocket".as_ref()));
+    assert_eq!(response.selected_protocol(), None);
+
+    let mut server = server.await.unwrap().unwrap();
+    client
+      .write_frame(Frame::text(b"Hi".as_ref().into()))
+      .await
+      .unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(&frame.payload[..], b"Hi");
+  }
+
+  #[tokio::test]
+  async fn client_raw_invalid_accept() {
+    let (stream, mut peer) = tokio::io::duplex(1024);
+    peer
+      .write_all(
+        b"HTTP/1.1 101 Switching Protocols\r\n\
+          Upgrade: websocket\r\n\
+          Connection: Upgrade\r\n\
+          Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+      )
+      .await
+      .unwrap();
+    assert!(matches!(
+      client_raw(stream, "/", "example.com", &[]).await,
+      Err(WebSocketError::InvalidSecWebSocketAccept)
+    ));
+  }
+
+  #[tokio::test]
+  async fn client_raw_extensions() {
+    async fn connect(
+      offer: Option<&str>,
+      extensions: &str,
+    ) -> Result<WebSocket<tokio::io::DuplexStream>, WebSocketError> {
+      let (stream, mut peer) = tokio::io::duplex(1024);
+      let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+         Sec-WebSocket-Extensions: {extensions}\r\n\r\n"
+      );
+      peer.write_all(response.as_bytes()).await.unwrap();
+      let mut headers = vec![("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")];
+      headers.extend(offer.map(|offer| ("Sec-WebSocket-Extensions", offer)));
+      let (ws, _) = client_raw(stream, "/", "example.com", &headers).await?;
+      Ok(ws)
+    }
+
+    let offer = "permessage-deflate; client_max_window_bits";
+    let ws = connect(Some(offer), "permessage-deflate").await.unwrap();
+    assert!(ws.negotiated_extensions().permessage_deflate);
+
+    // Not offered, or a window the compressor can not honor.
+    for (offer, extensions) in [
+      (None, "permessage-deflate"),
+      (Some(offer), "permessage-deflate; client_max_window_bits=10"),
+    ] {
+      assert!(matches!(
+        connect(offer, extensions).await,
+        Err(WebSocketError::InvalidExtension)
+      ));
+    }
+  }
+
+  #[tokio::test]
+  async fn client_raw_reserved_header() {
+    let (stream, _peer) = tokio::io::duplex(1024);
+    assert!(matches!(
+      client_raw(stream, "/", "example.com", &[("connection", "close")]).await,
+      Err(WebSocketError::ReservedHeader(name)) if name == "connection"
+    ));
+  }
+
+  #[tokio::test]
+  async fn client_raw_line_breaks() {
+    let requests = [
+      ("/\r\nSec-WebSocket-Version: 8\r\n", "example.com", None),
+      ("/ HTTP/1.0", "example.com", None),
+      ("/", "example.com\r\nConnection: close", None),
+      (
+        "/",
+        "example.com",
+        Some(("Origin", "a\r\nConnection: close")),
+      ),
+      ("/", "example.com", Some(("Sec-WebSocket-Key", "a\nb"))),
+    ];
+    for (path, host, header) in requests {
+      let (stream, mut peer) = tokio::io::duplex(1024);
+      assert!(matches!(
+        client_raw(stream, path, host, header.as_slice()).await,
+        Err(WebSocketError::InvalidValue)
+      ));
+      // Nothing was written.
+      let mut request = Vec::new();
+      peer.read_to_end(&mut request).await.unwrap();
+      assert!(request.is_empty());
+    }
+  }
+
+  #[tokio::test]
+  async fn client_raw_key() {
+    let key = generate_key_from(*b"the sample nonce");
+    assert_eq!(key, "dGhlIHNhbXBsZSBub25jZQ==");
+
+    let (stream, mut peer) = tokio::io::duplex(1024);
+    peer
+      .write_all(
+        b"HTTP/1.1 101 Switching Protocols\r\n\
+          Upgrade: websocket\r\n\
+          Connection: Upgrade\r\n\
+          Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\
+          Sec-WebSocket-Protocol: chat\r\n\r\n",
+      )
+      .await
+      .unwrap();
+    let (ws, _) =
+      client_raw(stream, "/", "example.com", &[("Sec-WebSocket-Key", &key)])
+        .await
+        .unwrap();
+    assert_eq!(ws.selected_protocol(), Some("chat"));
+
+    let mut request = [0; 1024];
+    let n = peer.read(&mut request).await.unwrap();
+    let request = std::str::from_utf8(&request[..n]).unwrap();
+    assert_eq!(request.matches("Sec-WebSocket-Key").count(), 1);
+    assert!(request.contains("Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n"));
+  }
+
+  #[test]
+  fn accept_key_guid() {
+    // The example of RFC 6455, section 1.3.
+    let key = "dGhlIHNhbXBsZSBub25jZQ==";
+    assert_eq!(accept_key(key), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(accept_key(key.as_bytes()), accept_key(key));
+    assert_eq!(accept_key_with_guid(key, WEBSOCKET_GUID), accept_key(key));
+    assert_ne!(accept_key_with_guid(key, "not-the-guid"), accept_key(key));
+  }
+
+  #[test]
+  fn handshake_response() {
+    let header = |name: &str, value: &str| (name.into(), value.into());
+    let response = HandshakeResponse::new(
+      101,
+      vec![
+        header("Sec-WebSocket-Protocol", " chat "),
+        header("Sec-WebSocket-Extensions", "permessage-deflate"),
+        header("sec-websocket-extensions", "x-unknown"),
+      ],
+    );
+    assert!(matches!(response, Err(WebSocketError::InvalidExtension)));
+
+    let response = HandshakeResponse::new(
+      101,
+      vec![
+        header("Sec-WebSocket-Protocol", " chat "),
+        header("Sec-WebSocket-Extensions", "permessage-deflate"),
+      ],
+    )
+    .unwrap();
+    assert_eq!(response.selected_protocol(), Some("chat"));
+    assert!(response.selected_extensions().permessage_deflate);
+
+    for protocols in [&["chat, superchat"][..], &["chat", "superchat"], &[""]] {
+      let headers = protocols
+        .iter()
+        .map(|value| header("Sec-WebSocket-Protocol", value))
+        .collect();
+      let response = HandshakeResponse::new(101, headers).unwrap();
+      assert_eq!(response.selected_protocol(), None);
+    }
+  }
+
+  #[tokio::test]
+  async fn server_handshake_invalid() {
+    let (mut client, stream) = tokio::io::duplex(1024);
+    client
+      .write_all(
+        b"GET / HTTP/1.1\r\n\
+          Upgrade: websocket\r\n\
+          Connection: Upgrade\r\n\
+          Sec-WebSocket-Version: 13\r\n\r\n",
+      )
+      .await
+      .unwrap();
+    assert!(matches!(
+      server(stream).await,
+      Err(WebSocketError::MissingSecWebSocketKey)
+    ));
+  }
+}
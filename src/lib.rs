@@ -73,7 +73,9 @@
 //! }
 //! ```
 //!
-//! _permessage-deflate is not supported yet._
+//! _permessage-deflate is not negotiated by the upgrade helpers. Once it has
+//! been agreed on, enable it with `WebSocket::set_extensions` and send
+//! compressed messages with `MessageBuilder::compress`._
 //!
 //! ## HTTP Upgrades
 //!
@@ -147,18 +149,44 @@
 //!   }
 //! }
 //! ```
+//!
+//! ## Handshakes without hyper
+//!
+//! Enable the `handshake` feature alone to accept connections on a plain
+//! stream with [`handshake::server`], or to connect with
+//! [`handshake::client_raw`], without depending on hyper.
+//!
+//! ## Tracing
+//!
+//! Enable the `tracing` feature to emit a [tracing](https://docs.rs/tracing)
+//! event at the `TRACE` level for every frame read and written, with the
+//! role of the connection, the opcode, the payload length and the fin, mask
+//! and compression bits. Nothing is emitted, and nothing is compiled in, when
+//! the feature is off.
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 mod close;
+mod deflate;
 mod error;
+mod extension;
 mod fragment;
 mod frame;
+mod guard;
 /// Client handshake.
-#[cfg(feature = "upgrade")]
-#[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
+#[cfg(feature = "handshake")]
+#[cfg_attr(docsrs, doc(cfg(feature = "handshake")))]
 pub mod handshake;
 mod mask;
+mod message;
+mod rate;
+mod reader;
+mod reconnect;
+mod stream;
+/// In-memory streams for testing.
+#[cfg(feature = "testing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "testing")))]
+pub mod testing;
 /// HTTP upgrades.
 #[cfg(feature = "upgrade")]
 #[cfg_attr(docsrs, doc(cfg(feature = "upgrade")))]
@@ -166,29 +194,51 @@ pub mod upgrade;
 
 use bytes::Buf;
 
+use bytes::Bytes;
 use bytes::BytesMut;
 #[cfg(feature = "unstable-split")]
 use std::future::Future;
+use std::time::Duration;
 
 use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
 
-use miniz_oxide::DataFormat;
-use miniz_oxide::inflate::stream::InflateState;
+use crate::deflate::Deflate;
+use crate::deflate::Inflate;
+use crate::rate::RateLimiter;
 
 pub use crate::close::CloseCode;
+pub use crate::close::CloseFrame;
+pub use crate::close::CloseReply;
+pub use crate::close::CloseState;
 pub use crate::error::WebSocketError;
+pub use crate::extension::ExtensionConfig;
 pub use crate::fragment::FragmentCollector;
 #[cfg(feature = "unstable-split")]
 pub use crate::fragment::FragmentCollectorRead;
+pub use crate::frame::CompressionStats;
+pub use crate::frame::Event;
 pub use crate::frame::Frame;
+pub use crate::frame::FrameHeader;
 pub use crate::frame::OpCode;
 pub use crate::frame::Payload;
+pub use crate::frame::UnknownOpcode;
+pub use crate::frame::WriteStats;
+pub use crate::guard::CloseGuard;
 pub use crate::mask::unmask;
-
-#[derive(Copy, Clone, PartialEq)]
+pub use crate::message::MessageBuilder;
+pub use crate::rate::RateLimit;
+pub use crate::reader::PayloadReader;
+pub use crate::reconnect::Backoff;
+pub use crate::reconnect::ReconnectEvent;
+pub use crate::reconnect::ReconnectingWebSocket;
+pub use crate::stream::PongOverflow;
+pub use crate::stream::WebSocketStream;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Role {
   Server,
   Client,
@@ -200,19 +250,60 @@ pub(crate) struct WriteHalf {
   vectored: bool,
   auto_apply_mask: bool,
   writev_threshold: usize,
+  copy_threshold: Option<usize>,
   write_buffer: Vec<u8>,
+  compression_level: u8,
+  compression_min_size: usize,
+  compressor: Option<Box<dyn Deflate>>,
+  no_context_takeover: bool,
+  deflate_frame: bool,
+  stats: CompressionStats,
+  write_stats: WriteStats,
+  fragment_size: Option<usize>,
+  close_reply_timeout: Option<Duration>,
+  corked: Option<Vec<u8>>,
+  rate_limiter: Option<RateLimiter>,
+  last_write: Option<Instant>,
 }
 
+type HeaderHook =
+  Box<dyn FnMut(&FrameHeader) -> Result<(), CloseCode> + Send + Sync>;
+
 pub(crate) struct ReadHalf {
   role: Role,
   auto_apply_mask: bool,
   auto_close: bool,
   auto_pong: bool,
-  writev_threshold: usize,
+  forward_pongs: bool,
+  allow_reserved_bits: bool,
+  validate_text: bool,
+  close_reply: CloseReply,
+  unknown_opcode: UnknownOpcode,
+  close_received: bool,
   max_message_size: usize,
+  max_text_size: Option<usize>,
+  max_binary_size: Option<usize>,
+  fragment_opcode: Option<OpCode>,
+  header_hook: Option<HeaderHook>,
+  header_checked: bool,
+  max_header_reads: Option<usize>,
+  header_reads: usize,
+  utf8_checked: usize,
   buffer: BytesMut,
-
-  state: InflateState,
+  /// The capacity of `buffer` set with `set_read_buffer_capacity`, kept when it is reallocated.
+  buffer_capacity: usize,
+  streamed: usize,
+
+  extensions: ExtensionConfig,
+  state: Option<Box<dyn Inflate>>,
+  inflating: Option<OpCode>,
+  /// The buffer lent by `read_frame_into` to inflate the next payload into.
+  inflate_buf: Option<Vec<u8>>,
+  stats: CompressionStats,
+
+  idle_timeout: Option<Duration>,
+  last_read: Instant,
+  rate_limiter: Option<RateLimiter>,
 }
 
 #[cfg(feature = "unstable-split")]
@@ -258,9 +349,11 @@ impl<'f, S> WebSocketRead<S> {
     (self.stream, self.read_half)
   }
 
-  pub fn set_writev_threshold(&mut self, threshold: usize) {
-    self.read_half.writev_threshold = threshold;
-  }
+  /// Does nothing: the writev threshold only applies to writes, see `WebSocketWrite::set_writev_threshold`.
+  #[deprecated(
+    note = "the writev threshold no longer affects reads, set it on `WebSocketWrite`"
+  )]
+  pub fn set_writev_threshold(&mut self, _threshold: usize) {}
 
   /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the application will have to manually send close frames.
   ///
@@ -276,13 +369,85 @@ impl<'f, S> WebSocketRead<S> {
     self.read_half.auto_pong = auto_pong;
   }
 
+  /// See `WebSocket::set_allow_reserved_bits`.
+  pub fn set_allow_reserved_bits(&mut self, allow_reserved_bits: bool) {
+    self.read_half.allow_reserved_bits = allow_reserved_bits;
+  }
+
+  /// See `WebSocket::set_validate_text`.
+  pub fn set_validate_text(&mut self, validate_text: bool) {
+    self.read_half.validate_text = validate_text;
+  }
+
+  /// See `WebSocket::set_read_buffer_capacity`.
+  pub fn set_read_buffer_capacity(&mut self, capacity: usize) {
+    self.read_half.set_read_buffer_capacity(capacity);
+  }
+
+  /// Sets whether pong frames are returned by `read_frame`. When `false`, they are consumed and reading
+  /// continues; they still count as activity for the idle timeout.
+  ///
+  /// Default: `true`
+  pub fn set_forward_pongs(&mut self, forward_pongs: bool) {
+    self.read_half.forward_pongs = forward_pongs;
+  }
+
+  /// Sets the close frame sent in reply to the peer's close frame when auto-close is enabled.
+  ///
+  /// Default: `CloseReply::Echo`
+  pub fn set_close_reply(&mut self, close_reply: CloseReply) {
+    self.read_half.close_reply = close_reply;
+  }
+
+  /// See `WebSocket::set_on_unknown_opcode`.
+  pub fn set_on_unknown_opcode(&mut self, unknown_opcode: UnknownOpcode) {
+    self.read_half.unknown_opcode = unknown_opcode;
+  }
+
   /// Sets the maximum message size in bytes. If a message is received that is larger than this, the connection will be closed.
   ///
+  /// For compressed messages the limit applies to the inflated size, and inflating stops as soon as it is exceeded.
+  ///
   /// Default: 64 MiB
   pub fn set_max_message_size(&mut self, max_message_size: usize) {
     self.read_half.max_message_size = max_message_size;
   }
 
+  /// Sets the maximum size in bytes of text frames, including continuation frames of a text message. Exceeding it closes the connection with `CloseCode::Size`.
+  ///
+  /// Default: the maximum message size
+  pub fn set_max_text_size(&mut self, max_text_size: usize) {
+    self.read_half.max_text_size = Some(max_text_size);
+  }
+
+  /// Sets the maximum size in bytes of binary frames, including continuation frames of a binary message. Exceeding it closes the connection with `CloseCode::Size`.
+  ///
+  /// Default: the maximum message size
+  pub fn set_max_binary_size(&mut self, max_binary_size: usize) {
+    self.read_half.max_binary_size = Some(max_binary_size);
+  }
+
+  /// See `WebSocket::set_max_header_reads`.
+  pub fn set_max_header_reads(&mut self, max_header_reads: Option<usize>) {
+    self.read_half.max_header_reads = max_header_reads;
+  }
+
+  /// See `WebSocket::set_read_rate_limit`.
+  pub fn set_read_rate_limit(&mut self, limit: Option<RateLimit>) {
+    self.read_half.rate_limiter = limit.map(RateLimiter::new);
+  }
+
+  /// Sets a hook that is called with the header of every incoming frame before its payload is read.
+  ///
+  /// Returning `Err(code)` rejects the frame without buffering its payload: `read_frame` fails with
+  /// `WebSocketError::FrameRejected` and a close frame with `code` is sent to the peer.
+  pub fn set_frame_header_hook<F>(&mut self, hook: F)
+  where
+    F: FnMut(&FrameHeader) -> Result<(), CloseCode> + Send + Sync + 'static,
+  {
+    self.read_half.header_hook = Some(Box::new(hook));
+  }
+
   /// Sets whether to automatically apply the mask to the frame payload.
   ///
   /// Default: `true`
@@ -290,6 +455,26 @@ impl<'f, S> WebSocketRead<S> {
     self.read_half.auto_apply_mask = auto_apply_mask;
   }
 
+  /// Sets whether permessage-deflate was negotiated during the handshake, see `WebSocket::set_compression`.
+  pub fn set_compression(&mut self, compression: bool) {
+    self.read_half.set_compression(compression);
+  }
+
+  /// See `WebSocket::set_extensions`.
+  pub fn set_extensions(&mut self, extensions: ExtensionConfig) {
+    self.read_half.set_extensions(extensions);
+  }
+
+  /// See `WebSocket::negotiated_extensions`.
+  pub fn negotiated_extensions(&self) -> &ExtensionConfig {
+    &self.read_half.extensions
+  }
+
+  /// Resets the decompressor, see `WebSocket::reset_compression_context`.
+  pub fn reset_compression_context(&mut self) {
+    self.read_half.reset_compression_context();
+  }
+
   /// Reads a frame from the stream.
   pub async fn read_frame<R, E>(
     &mut self,
@@ -300,6 +485,10 @@ impl<'f, S> WebSocketRead<S> {
     E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
     R: Future<Output = Result<(), E>>,
   {
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+
     loop {
       let (res, obligated_send) =
         self.read_half.read_frame_inner(&mut self.stream).await;
@@ -316,6 +505,17 @@ impl<'f, S> WebSocketRead<S> {
 
 #[cfg(feature = "unstable-split")]
 impl<'f, S> WebSocketWrite<S> {
+  /// Sets the extensions negotiated during the handshake, see `WebSocket::set_extensions`. Only the
+  /// compression context takeover of this side applies to the write half.
+  pub fn set_extensions(&mut self, extensions: ExtensionConfig) {
+    self.write_half.set_extensions(extensions);
+  }
+
+  /// Resets the compressor, see `WebSocket::reset_compression_context`.
+  pub fn reset_compression_context(&mut self) {
+    self.write_half.reset_compression_context();
+  }
+
   /// Sets whether to use vectored writes. This option does not guarantee that vectored writes will be always used.
   ///
   /// Default: `true`
@@ -323,10 +523,45 @@ impl<'f, S> WebSocketWrite<S> {
     self.write_half.vectored = vectored;
   }
 
+  /// Pre-sizes the buffer frames are serialized into when they are not written with vectored writes, to avoid
+  /// reallocating it as larger frames are written.
+  ///
+  /// Default: 2 bytes, grown as needed
+  pub fn set_write_buffer_capacity(&mut self, capacity: usize) {
+    self.write_half.set_write_buffer_capacity(capacity);
+  }
+
+  /// Sets the deflate level used for compressed messages, from 0 (stored, no compression) to 9 (best
+  /// compression). Higher values are clamped to 9. Can be changed at any time, it applies to the next message.
+  ///
+  /// Default: 6
+  pub fn set_compression_level(&mut self, level: u8) {
+    self.write_half.set_compression_level(level);
+  }
+
+  /// Sets the minimum message size in bytes for compression. Shorter messages are sent uncompressed even if
+  /// compression was requested, as are messages that would grow when compressed.
+  ///
+  /// Default: 0
+  pub fn set_compression_min_size(&mut self, min_size: usize) {
+    self.write_half.compression_min_size = min_size;
+  }
+
+  /// See `WebSocket::set_writev_threshold`.
   pub fn set_writev_threshold(&mut self, threshold: usize) {
     self.write_half.writev_threshold = threshold;
   }
 
+  /// See `WebSocket::set_copy_threshold`.
+  pub fn set_copy_threshold(&mut self, threshold: Option<usize>) {
+    self.write_half.copy_threshold = threshold;
+  }
+
+  /// See `WebSocket::set_write_rate_limit`.
+  pub fn set_write_rate_limit(&mut self, limit: Option<RateLimit>) {
+    self.write_half.rate_limiter = limit.map(RateLimiter::new);
+  }
+
   /// Sets whether to automatically apply the mask to the frame payload.
   ///
   /// Default: `true`
@@ -334,10 +569,17 @@ impl<'f, S> WebSocketWrite<S> {
     self.write_half.auto_apply_mask = auto_apply_mask;
   }
 
+  /// Returns whether a close frame has been sent. Writing other frames fails after that, and writing
+  /// another close frame does nothing, so replying to the peer's close completes a simultaneous close.
   pub fn is_closed(&self) -> bool {
     self.write_half.closed
   }
 
+  /// See `WebSocket::write_stats`.
+  pub fn write_stats(&self) -> WriteStats {
+    self.write_half.write_stats
+  }
+
   pub async fn write_frame(
     &mut self,
     frame: Frame<'f>,
@@ -348,12 +590,38 @@ impl<'f, S> WebSocketWrite<S> {
     self.write_half.write_frame(&mut self.stream, frame).await
   }
 
+  pub async fn write_message(
+    &mut self,
+    message: MessageBuilder<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .write_message(&mut self.stream, message)
+      .await
+  }
+
   pub async fn flush(&mut self) -> Result<(), WebSocketError>
   where
     S: AsyncWrite + Unpin,
   {
     flush(&mut self.stream).await
   }
+
+  /// See `WebSocket::cork`.
+  pub fn cork(&mut self) {
+    self.write_half.cork();
+  }
+
+  /// See `WebSocket::uncork`.
+  pub async fn uncork(&mut self) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.write_half.uncork(&mut self.stream).await
+  }
 }
 
 #[inline]
@@ -364,12 +632,102 @@ where
   stream.flush().await.map_err(WebSocketError::IoError)
 }
 
+/// Writes the same already encoded frames to many connections concurrently and flushes them, returning
+/// the result of each write in the order of `writers`.
+///
+/// The frames are encoded once by the caller, for example with `Frame::write`, and shared as they are, so
+/// this is meant for server halves: frames sent by a client would need to be masked for each connection.
+/// As with `WebSocket::write_raw`, the bytes are not checked. A failed write does not stop the others.
+///
+/// Every write waits for its connection, so a single slow client holds up the returned future. With a
+/// `timeout`, writes that take longer fail with `WebSocketError::WriteTimeout` instead, possibly leaving a
+/// partial frame on the wire: such connections should be dropped.
+///
+/// # Example
+///
+/// ```
+/// use bytes::Bytes;
+/// use fastwebsockets::{broadcast, Frame, WebSocketWrite};
+/// use std::time::Duration;
+/// use tokio::io::WriteHalf;
+/// use tokio::net::TcpStream;
+///
+/// async fn publish(
+///   clients: &mut Vec<WebSocketWrite<WriteHalf<TcpStream>>>,
+///   message: &[u8],
+/// ) {
+///   let mut buf = Vec::new();
+///   Frame::text(message.into()).write(&mut buf);
+///   let frames = Bytes::from(buf);
+///
+///   let mut writers: Vec<_> = clients.iter_mut().collect();
+///   let results =
+///     broadcast(&frames, &mut writers, Some(Duration::from_secs(5))).await;
+///   // Drop the clients that could not be written to.
+///   let mut results = results.into_iter();
+///   clients.retain(|_| results.next().unwrap().is_ok());
+/// }
+/// ```
+#[cfg(feature = "unstable-split")]
+#[cfg_attr(docsrs, doc(cfg(feature = "unstable-split")))]
+pub async fn broadcast<S>(
+  frames: &Bytes,
+  writers: &mut [&mut WebSocketWrite<S>],
+  timeout: Option<Duration>,
+) -> Vec<Result<(), WebSocketError>>
+where
+  S: AsyncWrite + Unpin,
+{
+  let mut writes: Vec<_> = writers
+    .iter_mut()
+    .map(|ws| {
+      Box::pin(async move {
+        let write = async {
+          ws.write_half.write_raw(&mut ws.stream, frames).await?;
+          flush(&mut ws.stream).await
+        };
+        match timeout {
+          Some(timeout) => tokio::time::timeout(timeout, write)
+            .await
+            .map_err(|_| WebSocketError::WriteTimeout)?,
+          None => write.await,
+        }
+      })
+    })
+    .collect();
+
+  let mut results: Vec<_> = writes.iter().map(|_| None).collect();
+  std::future::poll_fn(|cx| {
+    let mut done = true;
+    for (write, result) in writes.iter_mut().zip(results.iter_mut()) {
+      if result.is_none() {
+        match write.as_mut().poll(cx) {
+          std::task::Poll::Ready(res) => *result = Some(res),
+          std::task::Poll::Pending => done = false,
+        }
+      }
+    }
+    if done {
+      std::task::Poll::Ready(())
+    } else {
+      std::task::Poll::Pending
+    }
+  })
+  .await;
+
+  results.into_iter().map(Option::unwrap).collect()
+}
+
 /// WebSocket protocol implementation over an async stream.
+///
+/// Each connection owns its read and write buffers, so a `WebSocket` is `Send` and `Sync` whenever `S` is.
+/// A partially read frame is kept in the connection's own buffer across awaits, so a pending `read_frame`
+/// future can safely be resumed on another worker thread of a multi-threaded runtime.
 pub struct WebSocket<S> {
   stream: S,
   write_half: WriteHalf,
   read_half: ReadHalf,
-
+  protocol: Option<String>,
 }
 
 impl<'f, S> WebSocket<S> {
@@ -400,9 +758,25 @@ impl<'f, S> WebSocket<S> {
       stream,
       write_half: WriteHalf::after_handshake(role),
       read_half: ReadHalf::after_handshake(role),
+      protocol: None,
     }
   }
 
+  /// Returns the subprotocol negotiated during the handshake, if any.
+  ///
+  /// It is set by `upgrade::upgrade_with_protocol` and by the client handshakes, from the
+  /// `Sec-WebSocket-Protocol` header of the response. Use `set_selected_protocol` after a handshake done
+  /// by other means.
+  pub fn selected_protocol(&self) -> Option<&str> {
+    self.protocol.as_deref()
+  }
+
+  /// Sets the subprotocol returned by `selected_protocol`. It is only informational: the framing does not
+  /// depend on it.
+  pub fn set_selected_protocol(&mut self, protocol: Option<String>) {
+    self.protocol = protocol;
+  }
+
   /// Split a [`WebSocket`] into a [`WebSocketRead`] and [`WebSocketWrite`] half. Note that the split version does not
   /// handle fragmented packets and you may wish to create a [`FragmentCollectorRead`] over top of the read half that
   /// is returned.
@@ -437,6 +811,34 @@ impl<'f, S> WebSocket<S> {
     self.stream
   }
 
+  /// Returns a reference to the underlying stream.
+  #[inline]
+  pub fn get_ref(&self) -> &S {
+    &self.stream
+  }
+
+  /// Returns a mutable reference to the underlying stream, for example to change socket options such as
+  /// `TcpStream::set_nodelay` during the connection.
+  ///
+  /// Reading from or writing to the stream directly desynchronizes the WebSocket framing: bytes already
+  /// buffered by the `WebSocket` are not seen by the stream, and data written to it is read by the peer as
+  /// frames.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::WebSocket;
+  /// use tokio::net::TcpStream;
+  ///
+  /// fn low_latency(ws: &mut WebSocket<TcpStream>) -> std::io::Result<()> {
+  ///   ws.get_mut().set_nodelay(true)
+  /// }
+  /// ```
+  #[inline]
+  pub fn get_mut(&mut self) -> &mut S {
+    &mut self.stream
+  }
+
   /// Consumes the `WebSocket` and returns the underlying stream.
   #[inline]
   pub(crate) fn into_parts_internal(self) -> (S, ReadHalf, WriteHalf) {
@@ -450,11 +852,67 @@ impl<'f, S> WebSocket<S> {
     self.write_half.vectored = vectored;
   }
 
+  /// Pre-sizes the buffer frames are serialized into when they are not written with vectored writes, to avoid
+  /// reallocating it as larger frames are written.
+  ///
+  /// Default: 2 bytes, grown as needed
+  pub fn set_write_buffer_capacity(&mut self, capacity: usize) {
+    self.write_half.set_write_buffer_capacity(capacity);
+  }
+
+  /// Sets the capacity of the buffer data is read into. Frames that do not fit grow it, so sizing it for the
+  /// typical frame avoids reallocations, while a smaller one saves memory on connections carrying small
+  /// frames. Bytes already buffered are kept.
+  ///
+  /// Default: 8192 bytes, grown as needed
+  pub fn set_read_buffer_capacity(&mut self, capacity: usize) {
+    self.read_half.set_read_buffer_capacity(capacity);
+  }
+
+  /// Sets the deflate level used for compressed messages, from 0 (stored, no compression) to 9 (best
+  /// compression). Higher values are clamped to 9. Can be changed at any time, it applies to the next message.
+  ///
+  /// Default: 6
+  pub fn set_compression_level(&mut self, level: u8) {
+    self.write_half.set_compression_level(level);
+  }
+
+  /// Sets the minimum message size in bytes for compression. Shorter messages are sent uncompressed even if
+  /// compression was requested, as are messages that would grow when compressed.
+  ///
+  /// Default: 0
+  pub fn set_compression_min_size(&mut self, min_size: usize) {
+    self.write_half.compression_min_size = min_size;
+  }
+
+  /// Sets the payload size above which frames are written with vectored writes, when they are enabled.
+  ///
+  /// It has no effect on reads: received payloads are never copied out of the read buffer, whatever their
+  /// size.
+  ///
+  /// Default: 1024
   pub fn set_writev_threshold(&mut self, threshold: usize) {
-    self.read_half.writev_threshold = threshold;
     self.write_half.writev_threshold = threshold;
   }
 
+  /// Sets the payload size above which frames that are not written with a vectored write have their
+  /// header and payload written with two writes, instead of copying the payload into the write buffer
+  /// first. This saves copying large payloads at the cost of an extra write call, which matters less
+  /// over a buffered stream.
+  ///
+  /// Default: `None`, payloads are always copied
+  pub fn set_copy_threshold(&mut self, threshold: Option<usize>) {
+    self.write_half.copy_threshold = threshold;
+  }
+
+  /// Sets the largest payload sent in a single frame by `send_text` and `send_binary`. Longer messages are
+  /// split into continuation frames.
+  ///
+  /// Default: `None`, messages are sent in a single frame
+  pub fn set_fragment_size(&mut self, fragment_size: Option<usize>) {
+    self.write_half.fragment_size = fragment_size;
+  }
+
   /// Sets whether to automatically close the connection when a close frame is received. When set to `false`, the application will have to manually send close frames.
   ///
   /// Default: `true`
@@ -469,25 +927,256 @@ impl<'f, S> WebSocket<S> {
     self.read_half.auto_pong = auto_pong;
   }
 
+  /// Sets whether to accept frames with the RSV2 or RSV3 bits set, for custom extensions. The bits are
+  /// returned in `Frame::rsv2` and `Frame::rsv3`, and are sent as set on written frames.
+  ///
+  /// Default: `false`, such frames fail with `WebSocketError::ReservedBitsNotZero`
+  pub fn set_allow_reserved_bits(&mut self, allow_reserved_bits: bool) {
+    self.read_half.allow_reserved_bits = allow_reserved_bits;
+  }
+
+  /// Sets whether the payload of text messages is checked to be valid UTF-8. When disabled, text frames
+  /// are returned as received and it is up to the application to validate them, for example a proxy that
+  /// forwards them without looking at their content. `FragmentCollector` then reassembles text messages
+  /// without checking them either. Close frame reasons are still validated.
+  ///
+  /// Disabling validation breaks RFC 6455 compliance, which requires failing the connection on invalid
+  /// UTF-8 text.
+  ///
+  /// Default: `true`
+  pub fn set_validate_text(&mut self, validate_text: bool) {
+    self.read_half.validate_text = validate_text;
+  }
+
+  /// Sets whether pong frames are returned by `read_frame`. When `false`, they are consumed and reading
+  /// continues; they still count as activity for the idle timeout.
+  ///
+  /// Default: `true`
+  pub fn set_forward_pongs(&mut self, forward_pongs: bool) {
+    self.read_half.forward_pongs = forward_pongs;
+  }
+
+  /// Sets the close frame sent in reply to the peer's close frame when auto-close is enabled.
+  ///
+  /// Default: `CloseReply::Echo`
+  pub fn set_close_reply(&mut self, close_reply: CloseReply) {
+    self.read_half.close_reply = close_reply;
+  }
+
+  /// Sets what to do with frames using one of the opcodes RFC 6455 reserves for future use.
+  ///
+  /// `UnknownOpcode::Deliver` returns them with `OpCode::Other`, for proxies and experimental protocols.
+  /// Reserved control opcodes are still subject to the rules of control frames. `FragmentCollector` returns
+  /// such frames as they are and does not reassemble fragmented ones, their continuation frames are returned
+  /// as they are too.
+  ///
+  /// Default: `UnknownOpcode::Error`, the read fails with `WebSocketError::InvalidValue`
+  pub fn set_on_unknown_opcode(&mut self, unknown_opcode: UnknownOpcode) {
+    self.read_half.unknown_opcode = unknown_opcode;
+  }
+
+  /// Sets how long the automatic reply to the peer's close frame may take to be written. When it expires,
+  /// `read_frame` fails with `WebSocketError::WriteTimeout` and the connection is considered closed, so a
+  /// peer that stopped reading can not block the read loop during shutdown.
+  ///
+  /// Default: `None`, no limit
+  pub fn set_close_reply_timeout(&mut self, timeout: Option<Duration>) {
+    self.write_half.close_reply_timeout = timeout;
+  }
+
   /// Sets the maximum message size in bytes. If a message is received that is larger than this, the connection will be closed.
   ///
+  /// For compressed messages the limit applies to the inflated size, and inflating stops as soon as it is exceeded.
+  ///
+  /// `read_frame` checks each frame on its own. `FragmentCollector` also checks the total size of fragmented
+  /// messages as they are assembled. Either way, `WebSocketError::FrameTooLarge` is returned and, if auto
+  /// close is enabled, a close frame with `CloseCode::Size` is sent first.
+  ///
   /// Default: 64 MiB
   pub fn set_max_message_size(&mut self, max_message_size: usize) {
     self.read_half.max_message_size = max_message_size;
   }
 
+  /// Sets the maximum size in bytes of text frames, including continuation frames of a text message. Exceeding it closes the connection with `CloseCode::Size`.
+  ///
+  /// Default: the maximum message size
+  pub fn set_max_text_size(&mut self, max_text_size: usize) {
+    self.read_half.max_text_size = Some(max_text_size);
+  }
+
+  /// Sets the maximum size in bytes of binary frames, including continuation frames of a binary message. Exceeding it closes the connection with `CloseCode::Size`.
+  ///
+  /// Default: the maximum message size
+  pub fn set_max_binary_size(&mut self, max_binary_size: usize) {
+    self.read_half.max_binary_size = Some(max_binary_size);
+  }
+
+  /// Sets how long the connection may stay idle, with no frame read or written, before `read_frame`
+  /// fails with `WebSocketError::IdleTimeout`. Pings and pongs count as activity.
+  ///
+  /// The connection is left open on timeout, send a close frame or drop it.
+  ///
+  /// Default: `None`
+  pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+    self.read_half.idle_timeout = idle_timeout;
+    self.read_half.last_read = Instant::now();
+    self.write_half.last_write = idle_timeout.map(|_| Instant::now());
+  }
+
+  /// Limits the rate of frames read from the peer. Past the limit, `read_frame` waits before reading the
+  /// next frame, which in turn slows the peer down once the socket buffers fill up. With
+  /// `RateLimit::max_throttle`, a peer staying over the limit for too long fails the read instead.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{RateLimit, WebSocket};
+  /// use std::time::Duration;
+  /// use tokio::net::TcpStream;
+  ///
+  /// fn limit(ws: &mut WebSocket<TcpStream>) {
+  ///   ws.set_read_rate_limit(Some(RateLimit {
+  ///     bytes_per_second: Some(1 << 20),
+  ///     frames_per_second: Some(100),
+  ///     max_throttle: Some(Duration::from_secs(10)),
+  ///   }));
+  /// }
+  /// ```
+  ///
+  /// Default: `None`
+  pub fn set_read_rate_limit(&mut self, limit: Option<RateLimit>) {
+    self.read_half.rate_limiter = limit.map(RateLimiter::new);
+  }
+
+  /// Limits the rate of frames written. Past the limit, writes wait before writing the next frame.
+  /// `RateLimit::max_throttle` does not apply to writes.
+  ///
+  /// Default: `None`
+  pub fn set_write_rate_limit(&mut self, limit: Option<RateLimit>) {
+    self.write_half.rate_limiter = limit.map(RateLimiter::new);
+  }
+
+  /// Sets how many reads may be spent completing the header of a frame once its first byte has arrived.
+  /// A peer sending the header a byte at a time past that fails the read with
+  /// `WebSocketError::TooManyHeaderReads`, and the connection is closed with `CloseCode::Policy` if auto
+  /// close is enabled.
+  ///
+  /// Headers are at most 14 bytes and well-behaved peers send them in one write, so a small limit such as
+  /// 4 is enough. Reads waiting for the next frame are not counted.
+  ///
+  /// Default: `None`, no limit
+  pub fn set_max_header_reads(&mut self, max_header_reads: Option<usize>) {
+    self.read_half.max_header_reads = max_header_reads;
+  }
+
+  /// Sets a hook that is called with the header of every incoming frame before its payload is read.
+  ///
+  /// Returning `Err(code)` rejects the frame without buffering its payload: `read_frame` fails with
+  /// `WebSocketError::FrameRejected` and a close frame with `code` is sent to the peer.
+  pub fn set_frame_header_hook<F>(&mut self, hook: F)
+  where
+    F: FnMut(&FrameHeader) -> Result<(), CloseCode> + Send + Sync + 'static,
+  {
+    self.read_half.header_hook = Some(Box::new(hook));
+  }
+
   /// Sets whether to automatically apply the mask to the frame payload.
   ///
+  /// When disabled on a server, frames are returned with their payload masked as received, along with
+  /// their masking key, so that a proxy can forward them verbatim: a client with auto apply mask disabled
+  /// writes them as they are. Text payloads and close frames are still validated and pings answered,
+  /// unmasked. Compressed messages are returned inflated and unmasked, and so are the messages assembled
+  /// by `FragmentCollector`.
+  ///
   /// Default: `true`
   pub fn set_auto_apply_mask(&mut self, auto_apply_mask: bool) {
     self.read_half.auto_apply_mask = auto_apply_mask;
     self.write_half.auto_apply_mask = auto_apply_mask;
   }
 
+  /// Sets whether permessage-deflate was negotiated during the handshake. Frames with RSV1 set are
+  /// inflated if it was, and rejected with `WebSocketError::ReservedBitsNotZero` otherwise.
+  ///
+  /// Default: `false`
+  pub fn set_compression(&mut self, compression: bool) {
+    self.read_half.set_compression(compression);
+  }
+
+  /// Sets the extensions negotiated during the handshake, for example from
+  /// `ExtensionConfig::parse`. This enables compression if permessage-deflate is in use, and makes
+  /// `write_message` reset the compressor after each message if this side negotiated no context takeover.
+  ///
+  /// `handshake::client` sets them from the server response.
+  pub fn set_extensions(&mut self, extensions: ExtensionConfig) {
+    self.read_half.set_extensions(extensions);
+    self.write_half.set_extensions(extensions);
+  }
+
+  /// Returns the extensions negotiated during the handshake.
+  pub fn negotiated_extensions(&self) -> &ExtensionConfig {
+    &self.read_half.extensions
+  }
+
+  /// Resets the permessage-deflate contexts, so that no dictionary built from previous messages is kept.
+  ///
+  /// The compressor is dropped, freeing its memory until the next compressed message is sent, and the next
+  /// message compressed does not refer to previous ones. The decompressor can only be reset when the peer
+  /// negotiated no context takeover and no compressed message is partially received, otherwise the
+  /// peer's next frames may refer to data it holds. In that case it is left as is.
+  pub fn reset_compression_context(&mut self) {
+    self.read_half.reset_compression_context();
+    self.write_half.reset_compression_context();
+  }
+
+  /// Returns whether a close frame has been sent. Writing other frames fails after that, and writing
+  /// another close frame does nothing, so replying to the peer's close completes a simultaneous close.
   pub fn is_closed(&self) -> bool {
     self.write_half.closed
   }
 
+  /// Returns the state of the closing handshake.
+  pub fn close_state(&self) -> CloseState {
+    CloseState::new(self.write_half.closed, self.read_half.close_received)
+  }
+
+  /// Returns the number of bytes received from the stream but not yet returned as frames.
+  pub fn buffered_len(&self) -> usize {
+    self.read_half.buffer.len()
+  }
+
+  /// Returns whether a complete frame is buffered, so that the next `read_frame` returns without
+  /// waiting on the stream, unless the frame is a ping or pong handled internally.
+  pub fn has_buffered_frame(&self) -> bool {
+    self.read_half.has_buffered_frame()
+  }
+
+  /// Feeds bytes already read from the stream by another layer, such as an HTTP server that read past the
+  /// upgrade request, so that they are parsed before anything read from the stream.
+  ///
+  /// Call it before the first read: the bytes are placed in front of those already buffered.
+  pub fn prepend_buffer(&mut self, bytes: Vec<u8>) {
+    let buffered = &self.read_half.buffer;
+    let mut buffer = BytesMut::with_capacity(bytes.len() + buffered.len());
+    buffer.extend_from_slice(&bytes);
+    buffer.extend_from_slice(buffered);
+    self.read_half.buffer = buffer;
+  }
+
+  /// Returns how the frames of this connection were written so far. Comparing them before and after a
+  /// write tells whether it used a vectored write.
+  pub fn write_stats(&self) -> WriteStats {
+    self.write_half.write_stats
+  }
+
+  /// Returns the number of bytes compressed and inflated so far on this connection.
+  pub fn compression_stats(&self) -> CompressionStats {
+    CompressionStats {
+      compressed_out: self.write_half.stats.compressed_out,
+      raw_out: self.write_half.stats.raw_out,
+      ..self.read_half.stats
+    }
+  }
+
   /// Writes a frame to the stream.
   ///
   /// # Example
@@ -516,84 +1205,664 @@ impl<'f, S> WebSocket<S> {
     Ok(())
   }
 
-  /// Flushes the data from the underlying stream.
-  ///
-  /// if the underlying stream is buffered (i.e: TlsStream<TcpStream>), it is needed to call flush
-  /// to be sure that the written frame are correctly pushed down to the bottom stream/channel.
+  /// Writes already encoded frames to the stream as they are, for example frames read verbatim by a proxy.
   ///
-  pub async fn flush(&mut self) -> Result<(), WebSocketError>
+  /// The bytes are not masked nor checked: the caller is responsible for writing complete, valid frames,
+  /// masked if this is a client. Close frames among them mark the connection as closed, like with
+  /// `write_frame`: frames after the first close fail with `WebSocketError::ConnectionClosed` before anything
+  /// is written, unless they are all close frames, which are then skipped.
+  pub async fn write_raw(&mut self, bytes: &[u8]) -> Result<(), WebSocketError>
   where
-    S: AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
   {
-    flush(&mut self.stream).await
+    self.write_half.write_raw(&mut self.stream, bytes).await
   }
 
-  /// Reads a frame from the stream.
-  ///
-  /// This method will unmask the frame payload. For fragmented frames, use `FragmentCollector::read_frame`.
+  /// Writes a frame like `write_frame`, in chunks of at most `chunk_size` payload bytes. After the header
+  /// and after each chunk, `progress` is called with the number of bytes written so far and the total size
+  /// of the frame, for example to report the progress of a large upload.
   ///
-  /// Text frames payload is guaranteed to be valid UTF-8.
+  /// Dropping the future stops the write at a chunk boundary, leaving a partial frame on the wire: the
+  /// connection should then be dropped.
   ///
   /// # Example
   ///
   /// ```
-  /// use fastwebsockets::{OpCode, WebSocket, Frame};
+  /// use fastwebsockets::{WebSocket, Frame};
   /// use tokio::net::TcpStream;
   /// use anyhow::Result;
   ///
-  /// async fn echo(
-  ///   ws: &mut WebSocket<TcpStream>
+  /// async fn upload(
+  ///   ws: &mut WebSocket<TcpStream>,
+  ///   file: Vec<u8>,
   /// ) -> Result<()> {
-  ///   let frame = ws.read_frame().await?;
-  ///   match frame.opcode {
-  ///     OpCode::Text | OpCode::Binary => {
-  ///       ws.write_frame(frame).await?;
-  ///     }
-  ///     _ => {}
-  ///   }
+  ///   let frame = Frame::binary(file.into());
+  ///   ws.write_frame_with_progress(frame, 64 * 1024, |written, total| {
+  ///     println!("{written}/{total} bytes sent");
+  ///   })
+  ///   .await?;
   ///   Ok(())
   /// }
   /// ```
-  pub async fn read_frame(&mut self) -> Result<Frame<'f>, WebSocketError>
+  pub async fn write_frame_with_progress<F>(
+    &mut self,
+    frame: Frame<'_>,
+    chunk_size: usize,
+    progress: F,
+  ) -> Result<(), WebSocketError>
   where
     S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(usize, usize),
   {
-    loop {
-      let (res, obligated_send) =
-        self.read_half.read_frame_inner(&mut self.stream).await;
-      let is_closed = self.write_half.closed;
-      if let Some(frame) = obligated_send {
-        if !is_closed {
-          self.write_half.write_frame(&mut self.stream, frame).await?;
-        }
-      }
-      if let Some(frame) = res? {
-        if is_closed && frame.opcode != OpCode::Close {
-          return Err(WebSocketError::ConnectionClosed);
+    self
+      .write_half
+      .write_frame_with_progress(&mut self.stream, frame, chunk_size, progress)
+      .await
+  }
+
+  /// Writes a frame like `write_frame`, failing with `WebSocketError::WriteTimeout` if it could not be
+  /// written by `deadline`.
+  ///
+  /// The frame may have been partially written when the deadline expires, the connection should then be
+  /// dropped.
+  pub async fn write_frame_deadline(
+    &mut self,
+    frame: Frame<'f>,
+    deadline: Instant,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    tokio::time::timeout_at(deadline, self.write_frame(frame))
+      .await
+      .map_err(|_| WebSocketError::WriteTimeout)?
+  }
+
+  /// Writes a message as a sequence of frames, see [`MessageBuilder`].
+  pub async fn write_message(
+    &mut self,
+    message: MessageBuilder<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    self
+      .write_half
+      .write_message(&mut self.stream, message)
+      .await
+  }
+
+  /// Sends a text message.
+  ///
+  /// The message is masked according to the role, compressed if permessage-deflate was negotiated, and
+  /// fragmented according to `set_fragment_size`.
+  pub async fn send_text(&mut self, text: &str) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let compress = self.read_half.extensions.permessage_deflate;
+    self
+      .write_half
+      .send(&mut self.stream, OpCode::Text, text.as_bytes(), compress)
+      .await
+  }
+
+  /// Sends a binary message, see `send_text`.
+  pub async fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let compress = self.read_half.extensions.permessage_deflate;
+    self
+      .write_half
+      .send(&mut self.stream, OpCode::Binary, data, compress)
+      .await
+  }
+
+  /// Flushes the data from the underlying stream.
+  ///
+  /// if the underlying stream is buffered (i.e: TlsStream<TcpStream>), it is needed to call flush
+  /// to be sure that the written frame are correctly pushed down to the bottom stream/channel.
+  ///
+  pub async fn flush(&mut self) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    flush(&mut self.stream).await
+  }
+
+  /// Holds written frames back until `uncork`, which writes them all with a single write instead of one
+  /// per frame, for example around a burst of small frames.
+  ///
+  /// This applies to every frame written in between, including messages, raw frames and the automatic
+  /// replies to pings and close frames sent while reading. `write_frame_with_progress` can not be held
+  /// back, it writes the frames held so far first.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{Frame, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn send_all(
+  ///   ws: &mut WebSocket<TcpStream>,
+  ///   updates: &[&[u8]],
+  /// ) -> Result<()> {
+  ///   ws.cork();
+  ///   for update in updates {
+  ///     ws.write_frame(Frame::binary((*update).into())).await?;
+  ///   }
+  ///   ws.uncork().await?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub fn cork(&mut self) {
+    self.write_half.cork();
+  }
+
+  /// Writes the frames held back since `cork` with a single write, and stops holding frames back. Does
+  /// nothing if `cork` was not called.
+  pub async fn uncork(&mut self) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.write_half.uncork(&mut self.stream).await
+  }
+
+  /// Reads a frame from the stream.
+  ///
+  /// This method will unmask the frame payload. For fragmented frames, use `FragmentCollector::read_frame`.
+  ///
+  /// Text frames payload is guaranteed to be valid UTF-8.
+  ///
+  /// After a close frame has been sent, frames from the peer are still returned until its close frame
+  /// arrives, as required by the closing handshake. Reading past the peer's close frame fails with
+  /// `WebSocketError::ConnectionClosed`.
+  ///
+  /// # Cancel safety
+  ///
+  /// The future can be dropped while it waits for data, for example in a `tokio::select!` branch or under a
+  /// timeout: the bytes of a partially received frame stay in the read buffer, and the next call resumes
+  /// parsing where this one stopped. The only exception is a cancellation while an automatic pong or close
+  /// reply is being written, which may leave part of it on the wire.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{OpCode, WebSocket, Frame};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn echo(
+  ///   ws: &mut WebSocket<TcpStream>
+  /// ) -> Result<()> {
+  ///   let frame = ws.read_frame().await?;
+  ///   match frame.opcode {
+  ///     OpCode::Text | OpCode::Binary => {
+  ///       ws.write_frame(frame).await?;
+  ///     }
+  ///     _ => {}
+  ///   }
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn read_frame(&mut self) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    // Nothing can follow the peer's close frame.
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+
+    loop {
+      let (res, obligated_send) = self
+        .read_half
+        .read_frame_idle(&mut self.stream, self.write_half.last_write)
+        .await;
+      if let Some(frame) = obligated_send {
+        if !self.write_half.closed {
+          self
+            .write_half
+            .write_obligated(&mut self.stream, frame)
+            .await?;
         }
+      }
+      if let Some(frame) = res? {
         break Ok(frame);
       }
     }
   }
+
+  /// Reads a frame like `read_frame`, writing its unmasked and inflated payload into `buf` and returning
+  /// its header.
+  ///
+  /// `buf` is cleared first and its allocation is reused: compressed payloads are inflated straight into it,
+  /// other payloads are unmasked in the read buffer and copied into it. A loop that reads into the same
+  /// buffer does not allocate for payloads once it has grown to the size of the largest one. The returned
+  /// `length` is the length of the payload written to `buf`.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{OpCode, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn count_bytes(ws: &mut WebSocket<TcpStream>) -> Result<usize> {
+  ///   let mut buf = Vec::with_capacity(4096);
+  ///   let mut total = 0;
+  ///   loop {
+  ///     let header = ws.read_frame_into(&mut buf).await?;
+  ///     if header.opcode == OpCode::Close {
+  ///       break Ok(total);
+  ///     }
+  ///     total += buf.len();
+  ///   }
+  /// }
+  /// ```
+  pub async fn read_frame_into(
+    &mut self,
+    buf: &mut Vec<u8>,
+  ) -> Result<FrameHeader, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    buf.clear();
+    self.read_half.inflate_buf = Some(std::mem::take(buf));
+    let res = self.read_frame().await;
+    // The buffer is only gone if a payload was inflated into it.
+    let lent = self.read_half.inflate_buf.take();
+    let frame = match res {
+      Ok(frame) => frame,
+      Err(e) => {
+        *buf = lent.unwrap_or_default();
+        return Err(e);
+      }
+    };
+
+    let header = frame.header();
+    match (lent, frame.payload) {
+      (None, Payload::Owned(payload)) => *buf = payload,
+      (lent, payload) => {
+        *buf = lent.unwrap_or_default();
+        buf.extend_from_slice(&payload);
+      }
+    }
+    Ok(header)
+  }
+
+  /// Reads a frame like `read_frame`, returning its header and its payload as `Bytes`.
+  ///
+  /// Uncompressed payloads are split off the read buffer, so they are returned without being copied
+  /// and can be sliced and forwarded cheaply, for example to a `tokio_util::codec` sink.
+  pub async fn read_frame_bytes(
+    &mut self,
+  ) -> Result<(FrameHeader, Bytes), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let frame = self.read_frame().await?;
+    Ok((frame.header(), frame.payload.into()))
+  }
+
+  /// Reads the header of the next frame and returns it along with a reader over its payload, so that
+  /// large frames can be processed without buffering them whole.
+  ///
+  /// The payload of a data frame is read from the connection as the `PayloadReader` is read. If it is
+  /// dropped before the end of the payload, the rest is discarded by the next read. Frames are still
+  /// limited by the maximum message size, raise it with `set_max_message_size` to accept larger ones.
+  ///
+  /// Control frames, compressed frames and frames with a reserved opcode are read whole and handled as
+  /// in `read_frame`, their reader serving the payload from memory. Fragmented messages are returned
+  /// frame by frame.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{OpCode, WebSocket};
+  /// use tokio::fs::File;
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn save(ws: &mut WebSocket<TcpStream>, file: &mut File) -> Result<()> {
+  ///   loop {
+  ///     let (header, mut payload) = ws.read_frame_streaming().await?;
+  ///     match header.opcode {
+  ///       OpCode::Binary | OpCode::Continuation => {
+  ///         tokio::io::copy(&mut payload, file).await?;
+  ///         if header.fin {
+  ///           return Ok(());
+  ///         }
+  ///       }
+  ///       OpCode::Close => return Ok(()),
+  ///       _ => {}
+  ///     }
+  ///   }
+  /// }
+  /// ```
+  pub async fn read_frame_streaming(
+    &mut self,
+  ) -> Result<(FrameHeader, PayloadReader<'_, S>), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+
+    loop {
+      match self.read_half.start_streaming(&mut self.stream).await {
+        Ok(Some((header, mask))) => {
+          let payload = PayloadReader::new(
+            &mut self.stream,
+            &mut self.read_half,
+            &header,
+            mask,
+          );
+          return Ok((header, payload));
+        }
+        Ok(None) => {}
+        Err(e) => {
+          if let Some(frame) = self.read_half.fail_close(&e) {
+            if !self.write_half.closed {
+              self
+                .write_half
+                .write_obligated(&mut self.stream, frame)
+                .await?;
+            }
+          }
+          return Err(e);
+        }
+      }
+
+      let (res, obligated_send) =
+        self.read_half.read_frame_inner(&mut self.stream).await;
+      if let Some(frame) = obligated_send {
+        if !self.write_half.closed {
+          self
+            .write_half
+            .write_obligated(&mut self.stream, frame)
+            .await?;
+        }
+      }
+      if let Some(frame) = res? {
+        let header = frame.header();
+        let payload = PayloadReader::buffered(
+          &mut self.stream,
+          &mut self.read_half,
+          frame.payload.into(),
+        );
+        return Ok((header, payload));
+      }
+    }
+  }
+
+  /// Reads a frame like `read_frame` and returns it as an `Event`, so that the peer's close frame is
+  /// matched as `Event::Close` with its parsed close code and reason rather than by opcode.
+  ///
+  /// Fails with `WebSocketError::InvalidCloseFrame`, `InvalidCloseCode` or `InvalidUTF8` for a malformed
+  /// close frame, which can only reach this point when auto close is disabled.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{Event, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn echo(ws: &mut WebSocket<TcpStream>) -> Result<()> {
+  ///   loop {
+  ///     match ws.read_event().await? {
+  ///       Event::Data(frame) => ws.write_frame(frame).await?,
+  ///       Event::Ping(_) | Event::Pong(_) => {}
+  ///       Event::Close(code, reason) => {
+  ///         println!("closed with {code:?}: {reason}");
+  ///         return Ok(());
+  ///       }
+  ///     }
+  ///   }
+  /// }
+  /// ```
+  pub async fn read_event(&mut self) -> Result<Event<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    Event::from_frame(self.read_frame().await?)
+  }
+
+  /// Returns the opcode of the next frame without reading it, waiting for its first byte if needed. The
+  /// next `read_frame` reads the frame.
+  ///
+  /// Pings and pongs handled internally are not returned by `read_frame`, so the frame it returns may differ
+  /// from the peeked one when they are.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{OpCode, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use anyhow::Result;
+  ///
+  /// async fn route(ws: &mut WebSocket<TcpStream>) -> Result<()> {
+  ///   if ws.peek_opcode().await? == OpCode::Close {
+  ///     // Shut down before the close frame is replied to.
+  ///   }
+  ///   let frame = ws.read_frame().await?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn peek_opcode(&mut self) -> Result<OpCode, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    if self.read_half.close_received {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+    self.read_half.peek_opcode(&mut self.stream).await
+  }
+
+  /// Reads a frame like `read_frame`, failing with `WebSocketError::ReadTimeout` if none is received by
+  /// `deadline`.
+  ///
+  /// A partially received frame is kept in the read buffer, so reading can be resumed after a timeout.
+  pub async fn read_frame_deadline(
+    &mut self,
+    deadline: Instant,
+  ) -> Result<Frame<'f>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    tokio::time::timeout_at(deadline, self.read_frame())
+      .await
+      .map_err(|_| WebSocketError::ReadTimeout)?
+  }
+
+  /// Reads and discards frames until the peer's close frame arrives, and returns its close code and reason.
+  ///
+  /// Use it after sending a close frame to finish the closing handshake. Pings are still answered while
+  /// draining. Fails with `WebSocketError::ReadTimeout` if the close frame is not received within
+  /// `timeout`, and with `WebSocketError::ConnectionReset` if the stream ends first.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::{Frame, WebSocket};
+  /// use tokio::net::TcpStream;
+  /// use std::time::Duration;
+  /// use anyhow::Result;
+  ///
+  /// async fn shutdown(ws: &mut WebSocket<TcpStream>) -> Result<()> {
+  ///   ws.write_frame(Frame::close(1000, b"bye")).await?;
+  ///   let (code, reason) = ws.drain(Duration::from_secs(5)).await?;
+  ///   println!("closed with {code:?}: {reason}");
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn drain(
+    &mut self,
+    timeout: Duration,
+  ) -> Result<(CloseCode, String), WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let deadline = Instant::now() + timeout;
+    loop {
+      let frame = self.read_frame_deadline(deadline).await?;
+      if frame.opcode == OpCode::Close {
+        let (code, reason) = CloseFrame::parse(&frame.payload)?;
+        return Ok((code, reason.to_owned()));
+      }
+    }
+  }
+
+  /// Reads the frames that are already fully buffered, without reading from the underlying stream.
+  ///
+  /// A single read from the stream can pull in several frames when the peer pipelines small messages.
+  /// Call this after `read_frame` to drain them in one go. Returns an empty `Vec` if no complete frame
+  /// is buffered. Pings and close frames are handled as in `read_frame`.
+  pub async fn read_buffered_frames(
+    &mut self,
+  ) -> Result<Vec<Frame<'f>>, WebSocketError>
+  where
+    S: AsyncRead + AsyncWrite + Unpin,
+  {
+    let mut frames = Vec::new();
+    while !self.read_half.close_received && self.read_half.has_buffered_frame()
+    {
+      let (res, obligated_send) =
+        self.read_half.read_frame_inner(&mut self.stream).await;
+      if let Some(frame) = obligated_send {
+        if !self.write_half.closed {
+          self
+            .write_half
+            .write_obligated(&mut self.stream, frame)
+            .await?;
+        }
+      }
+      if let Some(frame) = res? {
+        frames.push(frame);
+      }
+    }
+    Ok(frames)
+  }
 }
 
 const MAX_HEADER_SIZE: usize = 14;
 
+/// Emits a trace event for a frame read or written by a connection.
+#[cfg(feature = "tracing")]
+fn trace_frame(role: Role, direction: &'static str, header: &FrameHeader) {
+  tracing::trace!(
+    ?role,
+    direction,
+    opcode = ?header.opcode,
+    length = header.length,
+    fin = header.fin,
+    masked = header.masked,
+    compressed = header.compressed,
+    "websocket frame"
+  );
+}
+
 impl ReadHalf {
   pub fn after_handshake(role: Role) -> Self {
-    let buffer = BytesMut::with_capacity(8192);
-
-    let state = InflateState::new(DataFormat::Raw);
+    let buffer_capacity = 8192;
+    let buffer = BytesMut::with_capacity(buffer_capacity);
 
     Self {
       role,
       auto_apply_mask: true,
       auto_close: true,
       auto_pong: true,
-      writev_threshold: 1024,
+      forward_pongs: true,
+      allow_reserved_bits: false,
+      validate_text: true,
+      close_reply: CloseReply::Echo,
+      unknown_opcode: UnknownOpcode::Error,
+      close_received: false,
       max_message_size: 64 << 20,
+      max_text_size: None,
+      max_binary_size: None,
+      fragment_opcode: None,
+      header_hook: None,
+      header_checked: false,
+      max_header_reads: None,
+      header_reads: 0,
+      utf8_checked: 0,
       buffer,
-      state,
+      buffer_capacity,
+      streamed: 0,
+      extensions: ExtensionConfig::default(),
+      state: None,
+      inflating: None,
+      inflate_buf: None,
+      stats: CompressionStats::default(),
+      idle_timeout: None,
+      last_read: Instant::now(),
+      rate_limiter: None,
+    }
+  }
+
+  pub(crate) fn set_extensions(&mut self, extensions: ExtensionConfig) {
+    self.state = extensions.permessage_deflate.then(deflate::new_inflater);
+    // A compressed message being received can not be inflated by the new
+    // decompressor, if any.
+    self.inflating = None;
+    self.extensions = extensions;
+  }
+
+  /// Replaces the decompressor with a new one if the peer does not take over its compression context
+  /// and no compressed message is being received. Otherwise, the peer's next messages may refer to the
+  /// data it holds.
+  pub(crate) fn reset_compression_context(&mut self) {
+    let peer_no_context_takeover = match self.role {
+      Role::Server => self.extensions.client_no_context_takeover,
+      Role::Client => self.extensions.server_no_context_takeover,
+    };
+    if self.state.is_some()
+      && peer_no_context_takeover
+      && self.inflating.is_none()
+    {
+      self.state = Some(deflate::new_inflater());
+    }
+  }
+
+  pub(crate) fn set_read_buffer_capacity(&mut self, capacity: usize) {
+    self.buffer_capacity = capacity;
+    let mut buffer = BytesMut::with_capacity(capacity.max(self.buffer.len()));
+    buffer.extend_from_slice(&self.buffer);
+    self.buffer = buffer;
+  }
+
+  pub(crate) fn set_compression(&mut self, compression: bool) {
+    self.set_extensions(ExtensionConfig {
+      permessage_deflate: compression,
+      ..self.extensions
+    });
+  }
+
+  /// `read_frame_inner`, failing with `WebSocketError::IdleTimeout` once no frame has been read, nor
+  /// written at `last_write`, for the idle timeout.
+  pub(crate) async fn read_frame_idle<'f, S>(
+    &mut self,
+    stream: &mut S,
+    last_write: Option<Instant>,
+  ) -> (Result<Option<Frame<'f>>, WebSocketError>, Option<Frame<'f>>)
+  where
+    S: AsyncRead + Unpin,
+  {
+    let Some(idle_timeout) = self.idle_timeout else {
+      return self.read_frame_inner(stream).await;
+    };
+
+    let last_activity =
+      last_write.map_or(self.last_read, |t| t.max(self.last_read));
+    let read = self.read_frame_inner(stream);
+    match tokio::time::timeout_at(last_activity + idle_timeout, read).await {
+      Ok(read) => read,
+      Err(_) => (Err(WebSocketError::IdleTimeout), None),
     }
   }
 
@@ -610,72 +1879,408 @@ impl ReadHalf {
   where
     S: AsyncRead + Unpin,
   {
+    if let Err(e) = self.skip_streamed(stream).await {
+      return (Err(e), None);
+    }
+    if let Some(rate_limiter) = &self.rate_limiter {
+      rate_limiter.wait().await;
+    }
+    let fragment_opcode = self.fragment_opcode;
     let mut frame = match self.parse_frame_header(stream).await {
       Ok(frame) => frame,
-      Err(e) => return (Err(e), None),
+      Err(e) => return self.fail(e),
+    };
+    if let Some(rate_limiter) = &mut self.rate_limiter {
+      if !rate_limiter.record(1, frame.payload.len() as u64) {
+        return self.fail(WebSocketError::RateLimitExceeded);
+      }
+    }
+    if self.idle_timeout.is_some() {
+      self.last_read = Instant::now();
+    }
+
+    let message_opcode = match frame.opcode {
+      OpCode::Continuation => fragment_opcode.unwrap_or(frame.opcode),
+      opcode => opcode,
     };
+    if matches!(message_opcode, OpCode::Other(_))
+      && self.unknown_opcode == UnknownOpcode::IgnoreSkip
+    {
+      return (Ok(None), None);
+    }
 
-    if self.role == Role::Server && self.auto_apply_mask {
+    // With auto apply mask disabled, frames from the client are returned
+    // masked, as received, but their content is checked unmasked.
+    let masked = self.role == Role::Server && frame.header().masked;
+    if masked && self.auto_apply_mask {
       frame.unmask()
     };
 
-    if frame.compressed {
-        frame = match frame.inflate(&mut self.state) {
-            Ok(frame) => frame,
-            Err(e) => return (Err(e), None),
-        }
+    // The continuation frames of a compressed message do not have RSV1 set,
+    // but are part of the same deflate stream. Control frames in between are
+    // never compressed. With x-webkit-deflate-frame, every compressed frame
+    // has RSV1 set and ends its own flush block.
+    let per_frame = self.extensions.deflate_frame;
+    let inflate = if frame.compressed {
+      Some(frame.opcode)
+    } else if frame.opcode == OpCode::Continuation && !per_frame {
+      self.inflating
+    } else {
+      None
+    };
+
+    if let Some(opcode) = inflate {
+      // Inflated payloads are returned unmasked.
+      if masked && !self.auto_apply_mask {
+        frame.unmask();
+      }
+      if !per_frame {
+        self.inflating = if frame.fin { None } else { Some(opcode) };
+      }
+      let max_size = self.max_size(opcode);
+      let end = frame.fin || per_frame;
+      // RSV1 is only accepted, and `inflating` only set, while there is a
+      // decompressor: `set_extensions` clears both.
+      let state = self.state.as_mut().unwrap();
+      let mut payload = self.inflate_buf.take().unwrap_or_default();
+      if let Err(e) = state.inflate(&frame.payload, end, max_size, &mut payload)
+      {
+        return self.fail(e);
+      }
+      self.stats.compressed_in += frame.payload.len() as u64;
+      self.stats.decompressed_in += payload.len() as u64;
+      frame.set_unmasked_payload(Payload::Owned(payload));
+      frame.compressed = false;
+    }
+
+    if frame.opcode == OpCode::Close {
+      self.close_received = true;
+    }
+
+    // Only the payloads that are checked or answered here are unmasked.
+    let checked = match frame.opcode {
+      OpCode::Text => self.validate_text && frame.fin,
+      OpCode::Close => self.auto_close,
+      // The pong echoes the unmasked payload of the ping.
+      OpCode::Ping => self.auto_pong,
+      _ => false,
+    };
+    let remask =
+      masked && !self.auto_apply_mask && inflate.is_none() && checked;
+    if remask {
+      frame.unmask();
     }
 
-    match frame.opcode {
+    let (res, obligated_send) = match frame.opcode {
       OpCode::Close if self.auto_close => {
-        match frame.payload.len() {
-          0 => {}
-          1 => return (Err(WebSocketError::InvalidCloseFrame), None),
-          _ => {
-            let code = close::CloseCode::from(u16::from_be_bytes(
-              frame.payload[0..2].try_into().unwrap(),
-            ));
-
-            #[cfg(feature = "simd")]
-            if simdutf8::basic::from_utf8(&frame.payload[2..]).is_err() {
-              return (Err(WebSocketError::InvalidUTF8), None);
-            };
-
-            #[cfg(not(feature = "simd"))]
-            if std::str::from_utf8(&frame.payload[2..]).is_err() {
-              return (Err(WebSocketError::InvalidUTF8), None);
-            };
-
-            if !code.is_allowed() {
-              return (
-                Err(WebSocketError::InvalidCloseCode),
-                Some(Frame::close(1002, &frame.payload[2..])),
-              );
-            }
+        match CloseFrame::parse(&frame.payload) {
+          Ok(_) => {}
+          Err(WebSocketError::InvalidCloseCode) => {
+            return (
+              Err(WebSocketError::InvalidCloseCode),
+              Some(Frame::close(1002, &frame.payload[2..])),
+            );
           }
-        };
+          Err(e) => return (Err(e), None),
+        }
 
-        let obligated_send = Frame::close_raw(frame.payload.to_owned().into());
+        let obligated_send = match &self.close_reply {
+          CloseReply::Echo => Frame::close_raw(frame.payload.to_owned().into()),
+          CloseReply::Code(code, reason) => {
+            Frame::close((*code).into(), reason.as_bytes())
+          }
+          CloseReply::Empty => Frame::close_raw(Vec::new().into()),
+        };
         (Ok(Some(frame)), Some(obligated_send))
       }
       OpCode::Ping if self.auto_pong => {
         (Ok(None), Some(Frame::pong(frame.payload)))
       }
+      OpCode::Pong if !self.forward_pongs => (Ok(None), None),
       OpCode::Text => {
-        if frame.fin && !frame.is_utf8() {
+        if frame.fin && self.validate_text && !frame.is_utf8() {
           (Err(WebSocketError::InvalidUTF8), None)
         } else {
           (Ok(Some(frame)), None)
         }
       }
       _ => (Ok(Some(frame)), None),
+    };
+
+    match res {
+      Ok(Some(mut frame)) if remask => {
+        frame.mask();
+        (Ok(Some(frame)), obligated_send)
+      }
+      res => (res, obligated_send),
+    }
+  }
+
+  /// Returns the error along with the close frame it obligates, if any.
+  fn fail<'f>(
+    &self,
+    e: WebSocketError,
+  ) -> (Result<Option<Frame<'f>>, WebSocketError>, Option<Frame<'f>>) {
+    let obligated_send = self.fail_close(&e);
+    (Err(e), obligated_send)
+  }
+
+  /// Returns the close frame that failing with `e` obligates, if any.
+  fn fail_close(&self, e: &WebSocketError) -> Option<Frame<'static>> {
+    let code = match e {
+      WebSocketError::FrameTooLarge if self.auto_close => CloseCode::Size,
+      WebSocketError::FrameRejected(code) => *code,
+      WebSocketError::TooManyHeaderReads
+      | WebSocketError::RateLimitExceeded
+        if self.auto_close =>
+      {
+        CloseCode::Policy
+      }
+      _ => return None,
+    };
+    Some(Frame::close(code.into(), &[]))
+  }
+
+  /// Starts reading the next frame for `WebSocket::read_frame_streaming`, leaving its payload to be read
+  /// by a `PayloadReader`. Returns `None` if the frame has to be read whole by `read_frame_inner`: control
+  /// frames, compressed frames and those of a message with a reserved opcode.
+  async fn start_streaming<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<Option<(FrameHeader, Option<[u8; 4]>)>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    self.skip_streamed(stream).await?;
+    if let Some(rate_limiter) = &self.rate_limiter {
+      rate_limiter.wait().await;
+    }
+    let header = self.read_header(stream).await?;
+    if frame::is_control(header.opcode)
+      || header.compressed
+      || self.inflating.is_some()
+      || matches!(header.message_opcode, OpCode::Other(_))
+    {
+      return Ok(None);
+    }
+
+    if let Some(rate_limiter) = &mut self.rate_limiter {
+      if !rate_limiter.record(1, header.payload_len as u64) {
+        return Err(WebSocketError::RateLimitExceeded);
+      }
+    }
+    if self.idle_timeout.is_some() {
+      self.last_read = Instant::now();
     }
+
+    self.header_checked = false;
+    self.fragment_opcode = if header.fin {
+      None
+    } else {
+      Some(header.message_opcode)
+    };
+    self.buffer.advance(header.header_len);
+    self.streamed = header.payload_len;
+    Ok(Some((
+      FrameHeader {
+        fin: header.fin,
+        opcode: header.opcode,
+        masked: header.mask.is_some(),
+        compressed: false,
+        length: header.payload_len,
+      },
+      header.mask,
+    )))
+  }
+
+  /// Discards what is left of a payload handed out by `WebSocket::read_frame_streaming` but not read to
+  /// the end, so that the next frame can be read.
+  async fn skip_streamed<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    while self.streamed > 0 {
+      if self.buffer.is_empty() && stream.read_buf(&mut self.buffer).await? == 0
+      {
+        return Err(WebSocketError::UnexpectedEOF);
+      }
+      let n = self.streamed.min(self.buffer.len());
+      self.buffer.advance(n);
+      self.streamed -= n;
+    }
+    Ok(())
+  }
+
+  /// Returns the maximum frame size for frames of a message of type `opcode`.
+  fn max_size(&self, opcode: OpCode) -> usize {
+    match opcode {
+      OpCode::Text => self.max_text_size,
+      OpCode::Binary => self.max_binary_size,
+      _ => None,
+    }
+    .unwrap_or(self.max_message_size)
+  }
+
+  /// Waits for the first byte of the next frame and returns its opcode, without consuming anything.
+  async fn peek_opcode<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<OpCode, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    self.skip_streamed(stream).await?;
+    while self.buffer.is_empty() {
+      if stream.read_buf(&mut self.buffer).await? == 0 {
+        return Err(WebSocketError::ConnectionReset);
+      }
+    }
+    self.opcode(self.buffer[0])
+  }
+
+  /// Returns the opcode of the frame starting with `byte`, applying the policy for reserved opcodes.
+  fn opcode(&self, byte: u8) -> Result<OpCode, WebSocketError> {
+    match OpCode::from_bits(byte) {
+      OpCode::Other(_) if self.unknown_opcode == UnknownOpcode::Error => {
+        Err(WebSocketError::InvalidValue)
+      }
+      opcode => Ok(opcode),
+    }
+  }
+
+  /// Returns whether a complete frame is buffered, so that reading it does not touch the stream.
+  fn has_buffered_frame(&self) -> bool {
+    let buf = &self.buffer[..];
+    if self.streamed > 0 || buf.len() < 2 {
+      return false;
+    }
+
+    let masked = buf[1] & 0b10000000 != 0;
+    let extra = match buf[1] & 0x7F {
+      126 => 2,
+      127 => 8,
+      _ => 0,
+    };
+    let header_len = 2 + extra + masked as usize * 4;
+    if buf.len() < header_len {
+      return false;
+    }
+
+    let payload_len = match extra {
+      0 => u64::from(buf[1] & 0x7F),
+      2 => u64::from(u16::from_be_bytes([buf[2], buf[3]])),
+      _ => u64::from_be_bytes(buf[2..10].try_into().unwrap()),
+    };
+    (buf.len() - header_len) as u64 >= payload_len
+  }
+
+  /// Validates the UTF-8 of the part of a text payload buffered so far, starting where the previous call
+  /// stopped. A character split at the end of the buffer is checked once the rest of it arrives.
+  fn check_partial_utf8(
+    &mut self,
+    header_len: usize,
+    payload_len: usize,
+    mask: Option<[u8; 4]>,
+  ) -> Result<(), WebSocketError> {
+    let available = payload_len.min(self.buffer.len() - header_len);
+    let payload = &self.buffer[header_len..header_len + available];
+
+    let mut unmasked = [0; 1024];
+    let mut pos = self.utf8_checked;
+    while pos < available {
+      let end = available.min(pos + unmasked.len());
+      let chunk = match mask {
+        Some(mask) => {
+          let chunk = &mut unmasked[..end - pos];
+          for (i, byte) in chunk.iter_mut().enumerate() {
+            *byte = payload[pos + i] ^ mask[(pos + i) % 4];
+          }
+          &chunk[..]
+        }
+        None => &payload[pos..end],
+      };
+
+      match frame::check_utf8(chunk) {
+        Ok(()) => pos = end,
+        // Cut in the middle of a character.
+        Err(e) if e.error_len.is_none() => {
+          pos += e.valid_up_to;
+          if end == available {
+            break;
+          }
+        }
+        Err(_) => return Err(WebSocketError::InvalidUTF8),
+      }
+    }
+
+    self.utf8_checked = pos;
+    Ok(())
+  }
+
+  async fn parse_frame_header<'a, S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<Frame<'a>, WebSocketError>
+  where
+    S: AsyncRead + Unpin,
+  {
+    let RawHeader {
+      fin,
+      rsv2,
+      rsv3,
+      opcode,
+      message_opcode,
+      compressed,
+      mask,
+      header_len,
+      payload_len,
+    } = self.read_header(stream).await?;
+
+    // Reserve a bit more to try to get next frame header and avoid a syscall to read it next time.
+    // Payloads are split off the connection buffer without copying, and once the previous ones have been
+    // dropped this reclaims their space instead of allocating, so the buffer is reused across frames.
+    try_reserve(
+      &mut self.buffer,
+      payload_len.saturating_add(MAX_HEADER_SIZE),
+      self.buffer_capacity,
+    )?;
+    // Text payloads are validated as they arrive, so that an invalid one is
+    // rejected without buffering all of it.
+    let check_utf8 =
+      self.validate_text && opcode == OpCode::Text && fin && !compressed;
+    while header_len + payload_len > self.buffer.remaining() {
+      if check_utf8 {
+        self.check_partial_utf8(header_len, payload_len, mask)?;
+      }
+      if stream.read_buf(&mut self.buffer).await? == 0 {
+        return Err(WebSocketError::UnexpectedEOF);
+      }
+    }
+
+    self.header_checked = false;
+    self.utf8_checked = 0;
+    if !frame::is_control(opcode) {
+      self.fragment_opcode = if fin { None } else { Some(message_opcode) };
+    }
+
+    // if we read too much it will stay in the buffer, for the next call to this method
+    self.buffer.advance(header_len);
+    let payload = self.buffer.split_to(payload_len);
+    let mut frame =
+      Frame::new(fin, opcode, mask, Payload::Bytes(payload), compressed);
+    frame.rsv2 = rsv2;
+    frame.rsv3 = rsv3;
+    Ok(frame)
   }
 
-  async fn parse_frame_header<'a, S>(
+  /// Reads and checks the header of the next frame, leaving it at the start of the buffer.
+  async fn read_header<S>(
     &mut self,
     stream: &mut S,
-  ) -> Result<Frame<'a>, WebSocketError>
+  ) -> Result<RawHeader, WebSocketError>
   where
     S: AsyncRead + Unpin,
   {
@@ -687,9 +2292,34 @@ impl ReadHalf {
       }};
     }
 
-    // Read the first two bytes
+    // Reads that continue a partially received header, waiting for the first
+    // byte of a frame is not bounded.
+    macro_rules! count_header_read {
+      () => {{
+        if self.buffer.remaining() > 0 {
+          self.header_reads += 1;
+          if self
+            .max_header_reads
+            .is_some_and(|max| self.header_reads > max)
+          {
+            return Err(WebSocketError::TooManyHeaderReads);
+          }
+        }
+      }};
+    }
+
+    // Nothing is consumed from the buffer until the whole frame is there, so a
+    // read that is cancelled half way can be resumed by the next call.
+
+    // Read the first two bytes. EOF before any byte of the frame is a clean
+    // disconnect, not a truncated frame.
     while self.buffer.remaining() < 2 {
-      eof!(stream.read_buf(&mut self.buffer).await?);
+      count_header_read!();
+      let n = stream.read_buf(&mut self.buffer).await?;
+      if n == 0 && self.buffer.remaining() == 0 {
+        return Err(WebSocketError::ConnectionReset);
+      }
+      eof!(n);
     }
 
     let fin = self.buffer[0] & 0b10000000 != 0;
@@ -697,76 +2327,177 @@ impl ReadHalf {
     let rsv2 = self.buffer[0] & 0b00100000 != 0;
     let rsv3 = self.buffer[0] & 0b00010000 != 0;
 
-    let mut compressed = false;
+    let opcode = self.opcode(self.buffer[0])?;
 
-    if rsv1 && !rsv2 && !rsv3 {
-      compressed = true;
-    } else if rsv1 || rsv2 || rsv3 {
+    // RSV1 marks the first frame of a compressed message, it is only allowed
+    // once permessage-deflate has been negotiated.
+    let compressed = rsv1
+      && self.state.is_some()
+      && (opcode != OpCode::Continuation || self.extensions.deflate_frame)
+      && !frame::is_control(opcode);
+    if (rsv1 && !compressed) || (!self.allow_reserved_bits && (rsv2 || rsv3)) {
       return Err(WebSocketError::ReservedBitsNotZero);
     }
-
-    let opcode = frame::OpCode::try_from(self.buffer[0] & 0b00001111)?;
     let masked = self.buffer[1] & 0b10000000 != 0;
 
     let length_code = self.buffer[1] & 0x7F;
+
+    // Control frames are checked from their first two bytes, before the rest
+    // of the header or anything of the payload is waited for. A length code
+    // above 125 announces an extended length, which they cannot have.
+    if frame::is_control(opcode) && !fin {
+      return Err(WebSocketError::ControlFrameFragmented);
+    }
+    if frame::is_control(opcode) && length_code > 125 {
+      return Err(WebSocketError::ControlFrameTooLarge);
+    }
+
     let extra = match length_code {
       126 => 2,
       127 => 8,
       _ => 0,
     };
 
-    self.buffer.advance(2);
-    while self.buffer.remaining() < extra + masked as usize * 4 {
+    let header_len = 2 + extra + masked as usize * 4;
+    while self.buffer.remaining() < header_len {
+      count_header_read!();
       eof!(stream.read_buf(&mut self.buffer).await?);
     }
+    self.header_reads = 0;
 
-    let payload_len: usize = match extra {
-      0 => usize::from(length_code),
-      2 => self.buffer.get_u16() as usize,
-      #[cfg(any(target_pointer_width = "64", target_pointer_width = "128"))]
-      8 => self.buffer.get_u64() as usize,
-      // On 32bit systems, usize is only 4bytes wide so we must check for usize overflowing
-      #[cfg(any(
-        target_pointer_width = "8",
-        target_pointer_width = "16",
-        target_pointer_width = "32"
-      ))]
-      8 => match usize::try_from(self.buffer.get_u64()) {
-        Ok(length) => length,
-        Err(_) => return Err(WebSocketError::FrameTooLarge),
-      },
+    let declared_len: u64 = match extra {
+      0 => u64::from(length_code),
+      2 => u64::from(u16::from_be_bytes([self.buffer[2], self.buffer[3]])),
+      8 => u64::from_be_bytes(self.buffer[2..10].try_into().unwrap()),
       _ => unreachable!(),
     };
 
+    // Continuation frames are limited by the type of the message they belong to.
+    let message_opcode = match opcode {
+      OpCode::Continuation => self.fragment_opcode.unwrap_or(opcode),
+      _ => opcode,
+    };
+
+    // Validate the declared length before anything is allocated for it. On
+    // 32bit systems, usize is only 4bytes wide so the conversion can fail too.
+    let payload_len = match usize::try_from(declared_len) {
+      Ok(len) if len < self.max_size(message_opcode) => len,
+      _ => return Err(WebSocketError::FrameTooLarge),
+    };
+
     let mask = if masked {
-      Some(self.buffer.get_u32().to_be_bytes())
+      Some(self.buffer[header_len - 4..header_len].try_into().unwrap())
     } else {
       None
     };
 
-    if frame::is_control(opcode) && !fin {
-      return Err(WebSocketError::ControlFrameFragmented);
+    #[cfg(feature = "tracing")]
+    if !self.header_checked {
+      trace_frame(
+        self.role,
+        "read",
+        &FrameHeader {
+          fin,
+          opcode,
+          masked,
+          compressed,
+          length: payload_len,
+        },
+      );
     }
 
-    if opcode == OpCode::Ping && payload_len > 125 {
-      return Err(WebSocketError::PingFrameTooLarge);
+    // Only run the hook once per frame, even if reading it is resumed.
+    if let (false, Some(hook)) =
+      (self.header_checked, self.header_hook.as_mut())
+    {
+      let header = FrameHeader {
+        fin,
+        opcode,
+        masked,
+        compressed,
+        length: payload_len,
+      };
+      hook(&header).map_err(WebSocketError::FrameRejected)?;
     }
+    self.header_checked = true;
+
+    Ok(RawHeader {
+      fin,
+      rsv2,
+      rsv3,
+      opcode,
+      message_opcode,
+      compressed,
+      mask,
+      header_len,
+      payload_len,
+    })
+  }
+}
 
-    if payload_len >= self.max_message_size {
-      return Err(WebSocketError::FrameTooLarge);
-    }
+/// A frame header parsed by `ReadHalf::read_header`.
+struct RawHeader {
+  fin: bool,
+  rsv2: bool,
+  rsv3: bool,
+  opcode: OpCode,
+  message_opcode: OpCode,
+  compressed: bool,
+  mask: Option<[u8; 4]>,
+  header_len: usize,
+  payload_len: usize,
+}
 
-    // Reserve a bit more to try to get next frame header and avoid a syscall to read it next time
-    self.buffer.reserve(payload_len + MAX_HEADER_SIZE);
-    while payload_len > self.buffer.remaining() {
-      eof!(stream.read_buf(&mut self.buffer).await?);
-    }
+/// Returns the opcodes of the frames encoded in `bytes` with the offset where
+/// each frame starts, stopping at the first incomplete one.
+fn raw_opcodes(mut bytes: &[u8]) -> impl Iterator<Item = (u8, usize)> + '_ {
+  let mut offset = 0;
+  std::iter::from_fn(move || {
+    let (&first, &second) = (bytes.first()?, bytes.get(1)?);
+    let (extra, len) = match second & 0x7F {
+      126 => (
+        2,
+        u64::from(u16::from_be_bytes(bytes.get(2..4)?.try_into().ok()?)),
+      ),
+      127 => (8, u64::from_be_bytes(bytes.get(2..10)?.try_into().ok()?)),
+      len => (0, u64::from(len)),
+    };
+    let header_len = 2 + extra + (second >> 7) as usize * 4;
+    let frame_len = usize::try_from(len).ok()?.checked_add(header_len)?;
+    bytes = bytes.get(frame_len..)?;
+    offset += frame_len;
+    Some((first & 0x0F, offset - frame_len))
+  })
+}
 
-    // if we read too much it will stay in the buffer, for the next call to this method
-    let payload = self.buffer.split_to(payload_len);
-    let frame = Frame::new(fin, opcode, mask, Payload::Bytes(payload), compressed);
-    Ok(frame)
+/// Fallible version of `BytesMut::reserve`. An allocation failure is reported
+/// as `WebSocketError::FrameTooLarge` instead of aborting the process.
+///
+/// A new allocation is never smaller than `min_capacity`, nor than the current
+/// capacity, so that reads are not limited to one frame after a payload still
+/// in use prevented reclaiming the buffer.
+fn try_reserve(
+  buffer: &mut BytesMut,
+  additional: usize,
+  min_capacity: usize,
+) -> Result<(), WebSocketError> {
+  if buffer.try_reclaim(additional) {
+    return Ok(());
   }
+
+  let capacity = buffer
+    .len()
+    .checked_add(additional)
+    .ok_or(WebSocketError::FrameTooLarge)?
+    .max(min_capacity)
+    .max(buffer.capacity());
+  let mut vec = Vec::new();
+  vec
+    .try_reserve_exact(capacity)
+    .map_err(|_| WebSocketError::FrameTooLarge)?;
+  vec.extend_from_slice(buffer);
+  *buffer = BytesMut::from(Bytes::from(vec));
+  Ok(())
 }
 
 impl WriteHalf {
@@ -777,8 +2508,74 @@ impl WriteHalf {
       auto_apply_mask: true,
       vectored: true,
       writev_threshold: 1024,
+      copy_threshold: None,
       write_buffer: Vec::with_capacity(2),
+      compression_level: 6,
+      compression_min_size: 0,
+      compressor: None,
+      no_context_takeover: false,
+      deflate_frame: false,
+      stats: CompressionStats::default(),
+      write_stats: WriteStats::default(),
+      fragment_size: None,
+      close_reply_timeout: None,
+      corked: None,
+      rate_limiter: None,
+      last_write: None,
+    }
+  }
+
+  pub(crate) fn set_extensions(&mut self, extensions: ExtensionConfig) {
+    // The no_context_takeover parameter of our side of the connection
+    // applies to the messages we compress.
+    self.no_context_takeover = match self.role {
+      Role::Server => extensions.server_no_context_takeover,
+      Role::Client => extensions.client_no_context_takeover,
+    };
+    self.deflate_frame = extensions.deflate_frame;
+  }
+
+  /// Drops the compressor, the next compressed message starts a new deflate stream.
+  pub(crate) fn reset_compression_context(&mut self) {
+    self.compressor = None;
+  }
+
+  pub fn set_compression_level(&mut self, level: u8) {
+    self.compression_level = level.min(9);
+    if let Some(compressor) = self.compressor.as_mut() {
+      compressor.set_level(self.compression_level);
+    }
+  }
+
+  pub fn set_write_buffer_capacity(&mut self, capacity: usize) {
+    let len = self.write_buffer.len();
+    self
+      .write_buffer
+      .reserve_exact(capacity.saturating_sub(len));
+  }
+
+  /// Checks that a frame with `opcode` can be written, a close frame marking the connection as closed.
+  /// Returns `false` for a close frame after one was already sent, which is skipped: when both peers
+  /// close at the same time, replying to the peer's close frame then completes the closing handshake
+  /// instead of sending a second close frame.
+  ///
+  /// `OpCode::Other` must hold a reserved value: the standard ones have their own variant and only 4 bits
+  /// are sent.
+  fn check_opcode(&mut self, opcode: OpCode) -> Result<bool, WebSocketError> {
+    if let OpCode::Other(value) = opcode {
+      if value > 0x0F || OpCode::from_bits(value) != opcode {
+        return Err(WebSocketError::InvalidValue);
+      }
+    }
+
+    if self.closed {
+      return match opcode {
+        OpCode::Close => Ok(false),
+        _ => Err(WebSocketError::ConnectionClosed),
+      };
     }
+    self.closed = opcode == OpCode::Close;
+    Ok(true)
   }
 
   /// Writes a frame to the provided stream.
@@ -790,23 +2587,318 @@ impl WriteHalf {
   where
     S: AsyncWrite + Unpin,
   {
+    self.throttle(1, frame.payload.len()).await;
+    if let Some(mut buf) = self.corked.take() {
+      let res = self.encode_frame(frame, &mut buf);
+      self.corked = Some(buf);
+      res?;
+      self.write_stats.copied_frames += 1;
+      return Ok(());
+    }
+
     if self.role == Role::Client && self.auto_apply_mask {
       frame.mask();
     }
 
-    if frame.opcode == OpCode::Close {
-      self.closed = true;
-    } else if self.closed {
-      return Err(WebSocketError::ConnectionClosed);
+    if !self.check_opcode(frame.opcode)? {
+      return Ok(());
     }
 
+    #[cfg(feature = "tracing")]
+    trace_frame(self.role, "write", &frame.header());
+
     if self.vectored && frame.payload.len() > self.writev_threshold {
       frame.writev(stream).await?;
+      self.write_stats.vectored_frames += 1;
+      self.write_stats.bytes_written += frame.encoded_len() as u64;
+    } else if self
+      .copy_threshold
+      .is_some_and(|threshold| frame.payload.len() > threshold)
+    {
+      let mut head = [0; MAX_HEADER_SIZE];
+      let size = frame.fmt_head(&mut head);
+      stream.write_all(&head[..size]).await?;
+      stream.write_all(&frame.payload).await?;
+      self.write_stats.split_frames += 1;
+      self.write_stats.bytes_written += (size + frame.payload.len()) as u64;
     } else {
       let text = frame.write(&mut self.write_buffer);
       stream.write_all(text).await?;
+      self.write_stats.copied_frames += 1;
+      self.write_stats.bytes_written += text.len() as u64;
+    }
+
+    if let Some(last_write) = &mut self.last_write {
+      *last_write = Instant::now();
+    }
+    Ok(())
+  }
+
+  /// Waits for the write rate limit, if any, and records `frames` frames of `bytes` payload bytes against
+  /// it.
+  async fn throttle(&mut self, frames: usize, bytes: usize) {
+    if let Some(rate_limiter) = &mut self.rate_limiter {
+      rate_limiter.wait().await;
+      rate_limiter.record(frames as u64, bytes as u64);
+    }
+  }
+
+  /// Holds frames back from now on: they are appended to a buffer, written by `uncork`.
+  pub(crate) fn cork(&mut self) {
+    self.corked.get_or_insert_with(Vec::new);
+  }
+
+  /// Writes the frames held back since `cork` with a single write, and stops holding them back.
+  pub(crate) async fn uncork<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    self.write_corked(stream).await?;
+    self.corked = None;
+    Ok(())
+  }
+
+  /// Writes the frames held back so far, if any, before a write that can not be held back.
+  async fn write_corked<S>(
+    &mut self,
+    stream: &mut S,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    let Some(buf) = self.corked.as_mut().filter(|buf| !buf.is_empty()) else {
+      return Ok(());
+    };
+    stream.write_all(buf).await?;
+    self.write_stats.bytes_written += buf.len() as u64;
+    buf.clear();
+
+    if let Some(last_write) = &mut self.last_write {
+      *last_write = Instant::now();
+    }
+    Ok(())
+  }
+
+  /// Writes a frame that `ReadHalf::read_frame_inner` obligated the caller to send, bounding the reply
+  /// to a close frame by the close reply timeout.
+  pub(crate) async fn write_obligated<S>(
+    &mut self,
+    stream: &mut S,
+    frame: Frame<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    match self.close_reply_timeout {
+      Some(timeout) if frame.opcode == OpCode::Close => {
+        // The write half is marked closed before the frame is written, so it
+        // stays closed if the write times out.
+        tokio::time::timeout(timeout, self.write_frame(stream, frame))
+          .await
+          .map_err(|_| WebSocketError::WriteTimeout)?
+      }
+      _ => self.write_frame(stream, frame).await,
+    }
+  }
+
+  /// Writes a frame to the provided stream in chunks of at most `chunk_size` payload bytes, calling
+  /// `progress` with the number of bytes written so far and the total after the header and each chunk.
+  pub async fn write_frame_with_progress<S, F>(
+    &mut self,
+    stream: &mut S,
+    mut frame: Frame<'_>,
+    chunk_size: usize,
+    mut progress: F,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+    F: FnMut(usize, usize),
+  {
+    if self.role == Role::Client && self.auto_apply_mask {
+      frame.mask();
+    }
+
+    if !self.check_opcode(frame.opcode)? {
+      return Ok(());
+    }
+    self.throttle(1, frame.payload.len()).await;
+    self.write_corked(stream).await?;
+
+    let mut head = [0; MAX_HEADER_SIZE];
+    let size = frame.fmt_head(&mut head);
+    let total = size + frame.payload.len();
+    stream.write_all(&head[..size]).await?;
+    let mut written = size;
+    progress(written, total);
+    for chunk in frame.payload.chunks(chunk_size.max(1)) {
+      stream.write_all(chunk).await?;
+      written += chunk.len();
+      progress(written, total);
+    }
+    self.write_stats.bytes_written += total as u64;
+
+    if let Some(last_write) = &mut self.last_write {
+      *last_write = Instant::now();
+    }
+    Ok(())
+  }
+
+  /// Writes already encoded frames to the provided stream as they are.
+  pub async fn write_raw<S>(
+    &mut self,
+    stream: &mut S,
+    bytes: &[u8],
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    let mut close = false;
+    let mut len = bytes.len();
+    for (opcode, start) in raw_opcodes(bytes) {
+      let is_close = opcode == u8::from(OpCode::Close);
+      if self.closed || close {
+        if !is_close {
+          return Err(WebSocketError::ConnectionClosed);
+        }
+        // As with `write_frame`, repeated close frames are skipped.
+        len = len.min(start);
+      }
+      close |= is_close;
+    }
+
+    self.closed |= close;
+    let bytes = &bytes[..len];
+    if bytes.is_empty() {
+      return Ok(());
+    }
+    self.throttle(raw_opcodes(bytes).count(), bytes.len()).await;
+    if let Some(buf) = self.corked.as_mut() {
+      buf.extend_from_slice(bytes);
+      return Ok(());
+    }
+    stream.write_all(bytes).await?;
+    self.write_stats.bytes_written += bytes.len() as u64;
+
+    if let Some(last_write) = &mut self.last_write {
+      *last_write = Instant::now();
+    }
+    Ok(())
+  }
+
+  /// Masks the frame as needed and appends it to `buf`, to be written later.
+  pub(crate) fn encode_frame(
+    &mut self,
+    mut frame: Frame<'_>,
+    buf: &mut Vec<u8>,
+  ) -> Result<(), WebSocketError> {
+    if self.role == Role::Client && self.auto_apply_mask {
+      frame.mask();
+    }
+
+    if !self.check_opcode(frame.opcode)? {
+      return Ok(());
+    }
+
+    #[cfg(feature = "tracing")]
+    trace_frame(self.role, "write", &frame.header());
+
+    frame.append_to(buf);
+    Ok(())
+  }
+
+  /// Sends `payload` as a message, split into frames of at most `fragment_size` bytes.
+  pub async fn send<S>(
+    &mut self,
+    stream: &mut S,
+    opcode: OpCode,
+    payload: &[u8],
+    compress: bool,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    let mut message = MessageBuilder::new(opcode).compress(compress);
+    match self.fragment_size.filter(|&size| size > 0) {
+      Some(size) if payload.len() > size => {
+        for chunk in payload.chunks(size) {
+          message = message.chunk(chunk);
+        }
+      }
+      _ => message = message.chunk(payload),
+    }
+    self.write_message(stream, message).await
+  }
+
+  /// Writes all the frames of a message to the provided stream at once.
+  pub async fn write_message<S>(
+    &mut self,
+    stream: &mut S,
+    message: MessageBuilder<'_>,
+  ) -> Result<(), WebSocketError>
+  where
+    S: AsyncWrite + Unpin,
+  {
+    let compressor = if message.is_compressed() {
+      let level = self.compression_level;
+      let compressor: &mut dyn Deflate = self
+        .compressor
+        .get_or_insert_with(|| deflate::new_deflater(level))
+        .as_mut();
+      Some(compressor)
+    } else {
+      None
+    };
+    let raw_len = message.len();
+    let frames = message
+      .per_frame(self.deflate_frame)
+      .into_frames(compressor, self.compression_min_size)?;
+    if frames[0].compressed && self.no_context_takeover {
+      // The whole message was compressed, the next one must not refer to it.
+      if let Some(compressor) = self.compressor.as_mut() {
+        compressor.reset();
+      }
+    }
+    if frames[0].compressed {
+      self.stats.raw_out += raw_len as u64;
+      self.stats.compressed_out += frames
+        .iter()
+        .map(|frame| frame.payload.len() as u64)
+        .sum::<u64>();
     }
 
+    let count = frames.len();
+    self.throttle(count, raw_len).await;
+    if let Some(mut buf) = self.corked.take() {
+      let res = frames
+        .into_iter()
+        .try_for_each(|frame| self.encode_frame(frame, &mut buf));
+      self.corked = Some(buf);
+      res?;
+      self.write_stats.copied_frames += count as u64;
+      return Ok(());
+    }
+
+    let mut buf = std::mem::take(&mut self.write_buffer);
+    buf.clear();
+    let res = match frames
+      .into_iter()
+      .try_for_each(|frame| self.encode_frame(frame, &mut buf))
+    {
+      Ok(()) => stream.write_all(&buf).await.map_err(WebSocketError::from),
+      Err(e) => Err(e),
+    };
+    let len = buf.len();
+    self.write_buffer = buf;
+    res?;
+    self.write_stats.copied_frames += count as u64;
+    self.write_stats.bytes_written += len as u64;
+
+    if let Some(last_write) = &mut self.last_write {
+      *last_write = Instant::now();
+    }
     Ok(())
   }
 }
@@ -816,27 +2908,600 @@ mod tests {
   use super::*;
 
   const _: () = {
-    const fn assert_unsync<S>() {
-      // Generic trait with a blanket impl over `()` for all types.
-      trait AmbiguousIfImpl<A> {
-        // Required for actually being able to reference the trait.
-        fn some_item() {}
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<WebSocket<tokio::net::TcpStream>>();
+    assert_send_sync::<FragmentCollector<tokio::net::TcpStream>>();
+  };
+
+  #[test]
+  fn write_buffer_capacity() {
+    let mut write_half = WriteHalf::after_handshake(Role::Server);
+    write_half.set_write_buffer_capacity(4096);
+    assert!(write_half.write_buffer.capacity() >= 4096);
+  }
+
+  #[test]
+  fn read_buffer_capacity() {
+    let mut read_half = ReadHalf::after_handshake(Role::Server);
+    read_half.buffer.extend_from_slice(b"\x81");
+    read_half.set_read_buffer_capacity(64 * 1024);
+    assert!(read_half.buffer.capacity() >= 64 * 1024);
+    read_half.set_read_buffer_capacity(512);
+    assert!(read_half.buffer.capacity() < 8192);
+    assert_eq!(&read_half.buffer[..], b"\x81");
+  }
+
+  #[tokio::test]
+  async fn read_buffer_capacity_kept() {
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.set_read_buffer_capacity(64 * 1024);
+
+    let (mut tx, mut rx) = tokio::io::duplex(128 * 1024);
+    let mut frame = vec![0x82, 126];
+    frame.extend_from_slice(&20000u16.to_be_bytes());
+    frame.resize(frame.len() + 20000, 0);
+    tx.write_all(&frame.repeat(5)).await.unwrap();
+
+    // The payloads are still in use when the last frame does not fit, the
+    // buffer is reallocated with the capacity that was set.
+    let mut payloads = Vec::new();
+    for _ in 0..5 {
+      let (res, _) = read_half.read_frame_inner(&mut rx).await;
+      payloads.push(Bytes::from(res.unwrap().unwrap().payload));
+    }
+    assert!(read_half.buffer.capacity() >= 16 * 1024);
+  }
+
+  #[tokio::test]
+  async fn max_header_reads() {
+    async fn read_dribbled(
+      max_header_reads: Option<usize>,
+    ) -> (
+      Result<Option<Frame<'static>>, WebSocketError>,
+      Option<Frame<'static>>,
+    ) {
+      // A single byte fits in the pipe, so every read returns one byte.
+      let (mut tx, mut rx) = tokio::io::duplex(1);
+      tokio::spawn(async move {
+        let mut data = vec![0x82, 126];
+        data.extend_from_slice(&200u16.to_be_bytes());
+        data.resize(data.len() + 200, 0);
+        let _ = tx.write_all(&data).await;
+      });
+
+      let mut read_half = ReadHalf::after_handshake(Role::Client);
+      read_half.max_header_reads = max_header_reads;
+      read_half.read_frame_inner(&mut rx).await
+    }
+
+    let (res, _) = read_dribbled(None).await;
+    assert_eq!(res.unwrap().unwrap().payload.len(), 200);
+    let (res, _) = read_dribbled(Some(3)).await;
+    assert_eq!(res.unwrap().unwrap().payload.len(), 200);
+
+    let (res, obligated_send) = read_dribbled(Some(2)).await;
+    assert!(matches!(res, Err(WebSocketError::TooManyHeaderReads)));
+    assert_eq!(&obligated_send.unwrap().payload[..], b"\x03\xf0");
+  }
+
+  #[tokio::test]
+  async fn oversized_declared_length() {
+    let mut header = vec![0x82, 127];
+    header.extend_from_slice(&u64::MAX.to_be_bytes());
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let (res, _) = read_half.read_frame_inner(&mut &header[..]).await;
+    assert!(matches!(res, Err(WebSocketError::FrameTooLarge)));
+  }
+
+  #[tokio::test]
+  async fn extended_length_above_u32() {
+    let len = u64::from(u32::MAX) + 1;
+    let mut header = vec![0x82, 127];
+    header.extend_from_slice(&len.to_be_bytes());
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.max_message_size = usize::MAX;
+    // Reject the frame once its header is parsed, before its payload is
+    // allocated.
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let hook_seen = seen.clone();
+    read_half.header_hook = Some(Box::new(move |header: &FrameHeader| {
+      *hook_seen.lock().unwrap() = Some(header.length);
+      Err(CloseCode::Size)
+    }));
+    let (res, _) = read_half.read_frame_inner(&mut &header[..]).await;
+
+    if cfg!(target_pointer_width = "64") {
+      assert!(matches!(res, Err(WebSocketError::FrameRejected(_))));
+      assert_eq!(*seen.lock().unwrap(), Some(len as usize));
+    } else {
+      assert!(matches!(res, Err(WebSocketError::FrameTooLarge)));
+      assert_eq!(*seen.lock().unwrap(), None);
+    }
+  }
+
+  async fn read_control_frame(opcode: u8, len: usize) -> WebSocketError {
+    let mut data = vec![0x80 | opcode, 126];
+    data.extend_from_slice(&(len as u16).to_be_bytes());
+    data.resize(data.len() + len, b'*');
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    res.err().unwrap()
+  }
+
+  #[tokio::test]
+  async fn per_type_max_size() {
+    let mut data = vec![0x01, 126];
+    data.extend_from_slice(&200u16.to_be_bytes());
+    data.resize(data.len() + 200, b'*');
+    data.extend_from_slice(&[0x80, 126]);
+    data.extend_from_slice(&200u16.to_be_bytes());
+    data.resize(data.len() + 200, b'*');
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.max_text_size = Some(100);
+    let (res, obligated_send) =
+      read_half.read_frame_inner(&mut &data[..]).await;
+    assert!(matches!(res, Err(WebSocketError::FrameTooLarge)));
+    let close = obligated_send.unwrap();
+    assert_eq!(close.opcode, OpCode::Close);
+    assert_eq!(&close.payload[..], &1009u16.to_be_bytes());
+
+    // Continuation frames of a text message are limited as text.
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.max_binary_size = Some(100);
+    let mut stream = &data[..];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert_eq!(res.unwrap().unwrap().opcode, OpCode::Text);
+    read_half.max_text_size = Some(100);
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(res, Err(WebSocketError::FrameTooLarge)));
+  }
+
+  #[tokio::test]
+  async fn frame_header_hook() {
+    let data = [0x82, 0x03, 0x01, 0x02, 0x03, 0x81, 0x01, b'a'];
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.header_hook =
+      Some(Box::new(|header: &FrameHeader| match header.opcode {
+        OpCode::Text => Err(CloseCode::Policy),
+        _ => Ok(()),
+      }));
+
+    let mut stream = &data[..];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert_eq!(&res.unwrap().unwrap().payload[..], &[1, 2, 3]);
+
+    let (res, obligated_send) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(
+      res,
+      Err(WebSocketError::FrameRejected(CloseCode::Policy))
+    ));
+    assert_eq!(&obligated_send.unwrap().payload[..], &1008u16.to_be_bytes());
+  }
+
+  #[tokio::test]
+  async fn eof_between_frames() {
+    let data = [0x82, 0x01, 0x01, 0x82];
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let mut stream = &data[..2];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(res, Err(WebSocketError::UnexpectedEOF)));
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let mut stream = &data[..3];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(res.unwrap().is_some());
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(res, Err(WebSocketError::ConnectionReset)));
+
+    let mut stream = &data[3..];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(res, Err(WebSocketError::UnexpectedEOF)));
+  }
+
+  #[tokio::test]
+  async fn partial_text_payload() {
+    let mask = [1, 2, 3, 4];
+    let masked = |payload: &[u8]| {
+      let mut header = vec![0x81, 0x80 | 126, 0x03, 0xe8];
+      header.extend_from_slice(&mask);
+      let payload = payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]);
+      header.extend(payload);
+      header
+    };
+
+    // The invalid byte is reported without waiting for the rest of the
+    // 1000 byte payload.
+    let (mut peer, mut stream) = tokio::io::duplex(4096);
+    peer.write_all(&masked(b"abc\xff")).await.unwrap();
+    let mut read_half = ReadHalf::after_handshake(Role::Server);
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(matches!(res, Err(WebSocketError::InvalidUTF8)));
+
+    // A character split between two reads is accepted.
+    let mut payload = "é".repeat(500).into_bytes();
+    let data = masked(&payload);
+    let (mut peer, mut stream) = tokio::io::duplex(4096);
+    let mut read_half = ReadHalf::after_handshake(Role::Server);
+    let read = tokio::spawn(async move {
+      let (res, _) = read_half.read_frame_inner(&mut stream).await;
+      res.unwrap().unwrap().payload.to_vec()
+    });
+    for chunk in data.chunks(99) {
+      peer.write_all(chunk).await.unwrap();
+      tokio::task::yield_now().await;
+    }
+    assert_eq!(read.await.unwrap(), payload);
+
+    // A payload received at once is checked when complete.
+    payload[999] = 0xff;
+    let mut data = &masked(&payload)[..];
+    let mut read_half = ReadHalf::after_handshake(Role::Server);
+    let (res, _) = read_half.read_frame_inner(&mut data).await;
+    assert!(matches!(res, Err(WebSocketError::InvalidUTF8)));
+  }
+
+  #[tokio::test]
+  async fn close_frame_too_large() {
+    let err = read_control_frame(OpCode::Close.into(), 126).await;
+    assert!(matches!(err, WebSocketError::ControlFrameTooLarge));
+  }
+
+  #[tokio::test]
+  async fn pong_frame_too_large() {
+    let err = read_control_frame(OpCode::Pong.into(), 126).await;
+    assert!(matches!(err, WebSocketError::ControlFrameTooLarge));
+  }
+
+  #[tokio::test]
+  async fn reserved_control_opcode() {
+    for opcode in 0xB..=0xF {
+      let err = read_control_frame(opcode, 0).await;
+      assert!(matches!(err, WebSocketError::InvalidValue));
+    }
+  }
+
+  #[tokio::test]
+  async fn rsv1_without_compression() {
+    let mut compressor = deflate::new_deflater(6);
+    let payload = compressor.deflate(b"hello", true).unwrap();
+    let mut data = vec![0xc1, payload.len() as u8];
+    data.extend_from_slice(&payload);
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    assert!(matches!(res, Err(WebSocketError::ReservedBitsNotZero)));
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.state = Some(deflate::new_inflater());
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    let frame = res.unwrap().unwrap();
+    assert_eq!(&frame.payload[..], b"hello");
+
+    // RSV1 is never valid on control frames.
+    let data = [0xc9, 0x00];
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    assert!(matches!(res, Err(WebSocketError::ReservedBitsNotZero)));
+  }
+
+  #[tokio::test]
+  async fn decompression_bomb() {
+    let mut compressor = deflate::new_deflater(9);
+    let payload = compressor.deflate(&vec![0; 16 << 20], true).unwrap();
+    assert!(payload.len() < 64 * 1024);
+    let mut data = vec![0xc2, 126];
+    data.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    data.extend_from_slice(&payload);
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.state = Some(deflate::new_inflater());
+    read_half.max_message_size = 1 << 20;
+    let (res, obligated_send) =
+      read_half.read_frame_inner(&mut &data[..]).await;
+    assert!(matches!(res, Err(WebSocketError::FrameTooLarge)));
+    assert_eq!(&obligated_send.unwrap().payload[..], b"\x03\xf1");
+  }
+
+  #[tokio::test]
+  async fn compressed_fragments_with_ping() {
+    let mut compressor = deflate::new_deflater(6);
+    let frames = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hello hello hello, ".as_ref())
+      .chunk(b"hello hello hello!".as_ref())
+      .compress(true)
+      .into_frames(Some(compressor.as_mut()), 0)
+      .unwrap();
+    assert!(frames[0].compressed);
+
+    let mut data = Vec::new();
+    let mut frames = frames.into_iter();
+    frames.next().unwrap().append_to(&mut data);
+    Frame::new(true, OpCode::Ping, None, b"ping".as_ref().into(), false)
+      .append_to(&mut data);
+    frames.next().unwrap().append_to(&mut data);
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.auto_pong = false;
+    read_half.state = Some(deflate::new_inflater());
+    let mut stream = &data[..];
+
+    let mut message = Vec::new();
+    for opcode in [OpCode::Text, OpCode::Ping, OpCode::Continuation] {
+      let (res, _) = read_half.read_frame_inner(&mut stream).await;
+      let frame = res.unwrap().unwrap();
+      assert_eq!(frame.opcode, opcode);
+      if opcode == OpCode::Ping {
+        assert_eq!(&frame.payload[..], b"ping");
+      } else {
+        message.extend_from_slice(&frame.payload);
+      }
+    }
+    assert_eq!(message, b"hello hello hello, hello hello hello!");
+  }
+
+  #[tokio::test]
+  async fn deflate_frame() {
+    let extensions = ExtensionConfig {
+      permessage_deflate: true,
+      deflate_frame: true,
+      ..Default::default()
+    };
+    let mut write_half = WriteHalf::after_handshake(Role::Server);
+    write_half.set_extensions(extensions);
+    let mut data = Vec::new();
+    let message = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hello hello hello, ".as_ref())
+      .chunk(b"hello hello hello!".as_ref())
+      .compress(true);
+    write_half.write_message(&mut data, message).await.unwrap();
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.set_extensions(extensions);
+    // Every frame has RSV1 set.
+    let second = 2 + data[1] as usize;
+    assert_eq!(data[0] & 0x40, 0x40);
+    assert_eq!(data[second] & 0x40, 0x40);
+
+    let mut stream = &data[..];
+    let mut message = Vec::new();
+    for opcode in [OpCode::Text, OpCode::Continuation] {
+      let (res, _) = read_half.read_frame_inner(&mut stream).await;
+      let frame = res.unwrap().unwrap();
+      assert_eq!(frame.opcode, opcode);
+      message.extend_from_slice(&frame.payload);
+    }
+    assert_eq!(message, b"hello hello hello, hello hello hello!");
+  }
+
+  #[tokio::test]
+  async fn write_no_context_takeover() {
+    async fn write_twice(extensions: ExtensionConfig) -> [Vec<u8>; 2] {
+      let mut write_half = WriteHalf::after_handshake(Role::Server);
+      write_half.set_extensions(extensions);
+      let mut out = [Vec::new(), Vec::new()];
+      for buf in &mut out {
+        let message = MessageBuilder::new(OpCode::Text)
+          .chunk(b"hello hello hello, ".as_ref())
+          .chunk(b"hello hello hello!".as_ref())
+          .compress(true);
+        write_half.write_message(buf, message).await.unwrap();
+      }
+      out
+    }
+
+    let extensions = ExtensionConfig {
+      permessage_deflate: true,
+      ..Default::default()
+    };
+    let [first, second] = write_twice(extensions).await;
+    assert!(second.len() < first.len());
+
+    let [first, second] = write_twice(ExtensionConfig {
+      server_no_context_takeover: true,
+      ..extensions
+    })
+    .await;
+    assert_eq!(first, second);
+
+    // Each message can be inflated on its own.
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    for data in [first, second] {
+      read_half.state = Some(deflate::new_inflater());
+      let mut stream = &data[..];
+      let mut message = Vec::new();
+      for _ in 0..2 {
+        let (res, _) = read_half.read_frame_inner(&mut stream).await;
+        message.extend_from_slice(&res.unwrap().unwrap().payload);
       }
+      assert_eq!(message, b"hello hello hello, hello hello hello!");
+    }
+  }
 
-      impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+  #[tokio::test]
+  async fn control_frame_too_large() {
+    // Masked close and pong frames from a client announcing 200 bytes. Only
+    // their first two bytes are needed to reject them.
+    for first in [0x88, 0x8A] {
+      for data in [&[first, 0xFE][..], &[first, 0xFE, 0, 200, 1, 2, 3, 4]] {
+        let mut read_half = ReadHalf::after_handshake(Role::Server);
+        let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+        assert!(matches!(res, Err(WebSocketError::ControlFrameTooLarge)));
+      }
+    }
 
-      // Used for the specialized impl when *all* traits in
-      // `$($t)+` are implemented.
-      #[allow(dead_code)]
-      struct Invalid;
+    // 125 bytes is the limit.
+    let mut data = vec![0x89, 0x80 | 125, 1, 2, 3, 4];
+    data.extend_from_slice(&[0; 125]);
+    let mut read_half = ReadHalf::after_handshake(Role::Server);
+    read_half.auto_pong = false;
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    assert_eq!(res.unwrap().unwrap().payload.len(), 125);
+  }
 
-      impl<T: ?Sized + Sync> AmbiguousIfImpl<Invalid> for T {}
+  #[tokio::test]
+  async fn compression_disabled_mid_message() {
+    let mut write_half = WriteHalf::after_handshake(Role::Server);
+    write_half.set_extensions(ExtensionConfig {
+      permessage_deflate: true,
+      ..Default::default()
+    });
+    let mut data = Vec::new();
+    let message = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hello ".as_ref())
+      .chunk(b"world".as_ref())
+      .compress(true);
+    write_half.write_message(&mut data, message).await.unwrap();
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.set_compression(true);
+    let mut stream = &data[..];
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert!(res.unwrap().is_some());
+
+    // The continuation is no longer inflated, rather than panicking.
+    read_half.set_compression(false);
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert_eq!(res.unwrap().unwrap().opcode, OpCode::Continuation);
+  }
 
-      // If there is only one specialized trait impl, type inference with
-      // `_` can be resolved and this can compile. Fails to compile if
-      // `$x` implements `AmbiguousIfImpl<Invalid>`.
-      let _ = <S as AmbiguousIfImpl<_>>::some_item;
+  #[tokio::test]
+  async fn reset_compression_context() {
+    let extensions = ExtensionConfig {
+      permessage_deflate: true,
+      ..Default::default()
+    };
+    let mut write_half = WriteHalf::after_handshake(Role::Server);
+    write_half.set_extensions(extensions);
+    let mut out = [Vec::new(), Vec::new(), Vec::new()];
+    for (i, buf) in out.iter_mut().enumerate() {
+      if i == 2 {
+        write_half.reset_compression_context();
+      }
+      let message = MessageBuilder::new(OpCode::Text)
+        .chunk(b"hello hello hello!".as_ref())
+        .compress(true);
+      write_half.write_message(buf, message).await.unwrap();
     }
-    assert_unsync::<WebSocket<tokio::net::TcpStream>>();
-  };
+    // The second message refers to the first, the third starts over.
+    assert!(out[1].len() < out[0].len());
+    assert_eq!(out[2], out[0]);
+
+    // The peer takes over its context, so the decompressor is kept.
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.set_extensions(extensions);
+    for data in &out {
+      let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+      assert_eq!(&res.unwrap().unwrap().payload[..], b"hello hello hello!");
+      read_half.reset_compression_context();
+    }
+  }
+
+  #[cfg(feature = "unstable-split")]
+  #[tokio::test]
+  async fn broadcast() {
+    let server = |capacity| {
+      let (client, server) = tokio::io::duplex(capacity);
+      let (_, write) = WebSocket::after_handshake(server, Role::Server)
+        .split(tokio::io::split);
+      (client, write)
+    };
+    let (mut a, mut write_a) = server(64);
+    let (b, mut write_b) = server(64);
+    // Never read, the frame does not fit.
+    let (_slow, mut write_slow) = server(4);
+    drop(b);
+
+    let mut buf = Vec::new();
+    Frame::text(b"hello".as_ref().into()).write(&mut buf);
+    let frames = Bytes::from(buf);
+    let results = super::broadcast(
+      &frames,
+      &mut [&mut write_a, &mut write_b, &mut write_slow],
+      Some(Duration::from_millis(50)),
+    )
+    .await;
+
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(WebSocketError::IoError(_))));
+    assert!(matches!(results[2], Err(WebSocketError::WriteTimeout)));
+
+    let mut received = [0; 7];
+    a.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"\x81\x05hello");
+  }
+
+  #[cfg(feature = "unstable-split")]
+  #[tokio::test]
+  async fn simultaneous_close_split() {
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let (mut read, write) =
+      WebSocket::after_handshake(server, Role::Server).split(tokio::io::split);
+    let write = std::sync::Arc::new(tokio::sync::Mutex::new(write));
+
+    client.write_frame(Frame::close(1000, b"")).await.unwrap();
+    let close = Frame::close(1001, b"");
+    write.lock().await.write_frame(close).await.unwrap();
+
+    // The reply to the client's close frame is skipped by the write half.
+    let frame = read
+      .read_frame(&mut |frame| {
+        let write = write.clone();
+        async move { write.lock().await.write_frame(frame).await }
+      })
+      .await
+      .unwrap();
+    assert_eq!(frame.opcode, OpCode::Close);
+    assert_eq!(write.lock().await.write_stats().bytes_written, 4);
+
+    let frame = client.read_frame().await.unwrap();
+    assert_eq!(&frame.payload[..], &1001u16.to_be_bytes());
+    assert_eq!(client.close_state(), CloseState::Closed);
+  }
+
+  #[tokio::test]
+  async fn unknown_opcode() {
+    // A fragmented message with opcode 0x3, a control frame with opcode 0xB
+    // and a text frame.
+    let data = [0x03, 1, b'x', 0x80, 1, b'y', 0x8B, 0, 0x81, 1, b'b'];
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    let (res, _) = read_half.read_frame_inner(&mut &data[..]).await;
+    assert!(matches!(res, Err(WebSocketError::InvalidValue)));
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.unknown_opcode = UnknownOpcode::IgnoreSkip;
+    let mut stream = &data[..];
+    for _ in 0..3 {
+      let (res, _) = read_half.read_frame_inner(&mut stream).await;
+      assert!(res.unwrap().is_none());
+    }
+    let (res, _) = read_half.read_frame_inner(&mut stream).await;
+    assert_eq!(res.unwrap().unwrap().opcode, OpCode::Text);
+
+    let mut read_half = ReadHalf::after_handshake(Role::Client);
+    read_half.unknown_opcode = UnknownOpcode::Deliver;
+    let mut stream = &data[..];
+    let expected = [
+      (false, OpCode::Other(0x3), &b"x"[..]),
+      (true, OpCode::Continuation, b"y"),
+      (true, OpCode::Other(0xB), b""),
+      (true, OpCode::Text, b"b"),
+    ];
+    for (fin, opcode, payload) in expected {
+      let (res, _) = read_half.read_frame_inner(&mut stream).await;
+      let frame = res.unwrap().unwrap();
+      assert_eq!((frame.fin, frame.opcode), (fin, opcode));
+      assert_eq!(&frame.payload[..], payload);
+    }
+
+    // Reserved control opcodes follow the rules of control frames.
+    let (res, _) = read_half.read_frame_inner(&mut &[0x0B, 0][..]).await;
+    assert!(matches!(res, Err(WebSocketError::ControlFrameFragmented)));
+  }
 }
@@ -0,0 +1,251 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::deflate::Deflate;
+use crate::frame::is_control;
+use crate::Frame;
+use crate::OpCode;
+use crate::Payload;
+use crate::WebSocketError;
+
+/// Builds a message out of several chunks, sent as one frame per chunk.
+///
+/// The first frame carries the message opcode, the following ones are `Continuation` frames and only the
+/// last one has FIN set. Frames are masked according to the role of the connection when written, and the
+/// message is compressed with permessage-deflate if requested, setting RSV1 on the first frame only.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{MessageBuilder, OpCode, WebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn send(
+///   ws: &mut WebSocket<TcpStream>
+/// ) -> Result<()> {
+///   let message = MessageBuilder::new(OpCode::Text)
+///     .chunk(b"Hello, ".as_ref())
+///     .chunk(b"world!".as_ref());
+///   ws.write_message(message).await?;
+///   Ok(())
+/// }
+/// ```
+pub struct MessageBuilder<'a> {
+  opcode: OpCode,
+  chunks: Vec<Payload<'a>>,
+  compress: bool,
+  per_frame: bool,
+}
+
+impl<'a> MessageBuilder<'a> {
+  /// Creates an empty message of type `opcode`.
+  pub fn new(opcode: OpCode) -> Self {
+    Self {
+      opcode,
+      chunks: Vec::new(),
+      compress: false,
+      per_frame: false,
+    }
+  }
+
+  /// Appends a chunk to the message. Each chunk is sent in its own frame.
+  pub fn chunk(mut self, chunk: impl Into<Payload<'a>>) -> Self {
+    self.chunks.push(chunk.into());
+    self
+  }
+
+  /// Sets whether to compress the message with permessage-deflate. Only enable it if the extension was
+  /// negotiated during the handshake.
+  ///
+  /// Default: `false`
+  pub fn compress(mut self, compress: bool) -> Self {
+    self.compress = compress;
+    self
+  }
+
+  /// Sets whether each frame is compressed on its own with RSV1 set, as x-webkit-deflate-frame does,
+  /// instead of the message as a whole.
+  pub(crate) fn per_frame(mut self, per_frame: bool) -> Self {
+    self.per_frame = per_frame;
+    self
+  }
+
+  /// Returns the total length of the chunks, before compression.
+  pub fn len(&self) -> usize {
+    self.chunks.iter().map(|chunk| chunk.len()).sum()
+  }
+
+  /// Returns whether the message has no payload.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Returns whether the message is to be compressed.
+  pub fn is_compressed(&self) -> bool {
+    self.compress
+  }
+
+  /// Turns the message into its frames, compressing the payloads with `compressor` if compression was
+  /// requested.
+  ///
+  /// Messages shorter than `min_size` and control messages are sent uncompressed. So is a message that
+  /// grows when compressed; the compressor is then reset, since the peer never sees the data that went
+  /// into it.
+  pub(crate) fn into_frames(
+    self,
+    compressor: Option<&mut dyn Deflate>,
+    min_size: usize,
+  ) -> Result<Vec<Frame<'a>>, WebSocketError> {
+    if is_control(self.opcode) && self.chunks.len() > 1 {
+      return Err(WebSocketError::ControlFrameFragmented);
+    }
+
+    let len = self.len();
+    let mut chunks = self.chunks;
+    if chunks.is_empty() {
+      chunks.push(Payload::Owned(Vec::new()));
+    }
+    let last = chunks.len() - 1;
+
+    let compressor = compressor
+      .filter(|_| self.compress && len >= min_size && !is_control(self.opcode));
+    let mut compressed = false;
+    if let Some(compressor) = compressor {
+      let deflated = chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+          compressor.deflate(chunk, i == last || self.per_frame)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+      if deflated.iter().map(Vec::len).sum::<usize>() < len {
+        chunks = deflated.into_iter().map(Payload::Owned).collect();
+        compressed = true;
+      } else {
+        compressor.reset();
+      }
+    }
+
+    let frames = chunks
+      .into_iter()
+      .enumerate()
+      .map(|(i, chunk)| {
+        let opcode = if i == 0 {
+          self.opcode
+        } else {
+          OpCode::Continuation
+        };
+        let rsv1 = compressed && (i == 0 || self.per_frame);
+        Frame::new(i == last, opcode, None, chunk, rsv1)
+      })
+      .collect();
+
+    Ok(frames)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use miniz_oxide::inflate::stream::InflateState;
+  use miniz_oxide::DataFormat;
+
+  #[test]
+  fn fragments() {
+    let frames = MessageBuilder::new(OpCode::Binary)
+      .chunk(b"one".as_ref())
+      .chunk(b"two".as_ref())
+      .chunk(b"six".as_ref())
+      .into_frames(None, 0)
+      .unwrap();
+
+    let expected = [
+      (false, OpCode::Binary, b"one"),
+      (false, OpCode::Continuation, b"two"),
+      (true, OpCode::Continuation, b"six"),
+    ];
+    assert_eq!(frames.len(), expected.len());
+    for (frame, (fin, opcode, payload)) in frames.iter().zip(expected) {
+      assert_eq!(frame.fin, fin);
+      assert_eq!(frame.opcode, opcode);
+      assert!(!frame.compressed);
+      assert_eq!(&frame.payload[..], payload);
+    }
+  }
+
+  #[test]
+  fn compressed_fragments() {
+    let mut compressor = crate::deflate::new_deflater(6);
+
+    let frames = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hello hello hello, ".as_ref())
+      .chunk(b"hello hello hello!".as_ref())
+      .compress(true)
+      .into_frames(Some(compressor.as_mut()), 0)
+      .unwrap();
+
+    assert_eq!(frames.len(), 2);
+    assert!(frames[0].compressed);
+    assert!(!frames[1].compressed);
+    assert!(!frames[0].fin);
+    assert!(frames[1].fin);
+
+    // The payloads of all the frames form a single deflate stream.
+    let payload: Vec<u8> = frames
+      .iter()
+      .flat_map(|frame| frame.payload.iter().copied())
+      .collect();
+    let frame = Frame::new(true, OpCode::Text, None, payload.into(), true);
+    let mut state = InflateState::new(DataFormat::Raw);
+    let inflated = frame.inflate(&mut state, 1024).unwrap();
+    assert_eq!(
+      &inflated.payload[..],
+      b"hello hello hello, hello hello hello!"
+    );
+  }
+
+  #[test]
+  fn fragmented_control_frame() {
+    let res = MessageBuilder::new(OpCode::Ping)
+      .chunk(b"a".as_ref())
+      .chunk(b"b".as_ref())
+      .into_frames(None, 0);
+    assert!(matches!(res, Err(WebSocketError::ControlFrameFragmented)));
+  }
+
+  #[test]
+  fn skip_compression() {
+    let mut compressor = crate::deflate::new_deflater(6);
+
+    let frames = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hello hello hello hello".as_ref())
+      .compress(true)
+      .into_frames(Some(compressor.as_mut()), 64)
+      .unwrap();
+    assert!(!frames[0].compressed);
+    assert_eq!(&frames[0].payload[..], b"hello hello hello hello");
+
+    // Too short to shrink when compressed.
+    let frames = MessageBuilder::new(OpCode::Text)
+      .chunk(b"hi".as_ref())
+      .compress(true)
+      .into_frames(Some(compressor.as_mut()), 0)
+      .unwrap();
+    assert!(!frames[0].compressed);
+    assert_eq!(&frames[0].payload[..], b"hi");
+  }
+}
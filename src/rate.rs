@@ -0,0 +1,145 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// A limit on the rate of frames and payload bytes of a connection, see `WebSocket::set_read_rate_limit`
+/// and `WebSocket::set_write_rate_limit`.
+///
+/// Each rate is enforced with a token bucket holding one second worth of tokens, so bursts up to the
+/// rate go through at once. Going over it delays the next frame until the bucket is back in credit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+  /// Payload bytes per second, `None` for no limit.
+  pub bytes_per_second: Option<u64>,
+  /// Frames per second, control frames included. `None` for no limit.
+  pub frames_per_second: Option<u64>,
+  /// For reads only: how long a peer may keep going over the limit before the read fails with
+  /// `WebSocketError::RateLimitExceeded` and the connection is closed with `CloseCode::Policy` if auto
+  /// close is enabled. `None` throttles the peer for as long as it takes.
+  pub max_throttle: Option<Duration>,
+}
+
+/// A token bucket refilled at `rate` tokens per second, up to `rate` tokens.
+struct Bucket {
+  rate: f64,
+  tokens: f64,
+  last: Instant,
+}
+
+impl Bucket {
+  fn new(per_second: u64, now: Instant) -> Self {
+    let rate = per_second.max(1) as f64;
+    Self {
+      rate,
+      tokens: rate,
+      last: now,
+    }
+  }
+
+  /// Takes `amount` tokens, going into debt if there are not enough, and returns how long until the
+  /// bucket is back in credit.
+  fn take(&mut self, amount: u64, now: Instant) -> Duration {
+    let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+    self.last = now;
+    self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+    self.tokens -= amount as f64;
+    if self.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-self.tokens / self.rate)
+    }
+  }
+}
+
+/// The state of a `RateLimit` on one direction of a connection.
+pub(crate) struct RateLimiter {
+  bytes: Option<Bucket>,
+  frames: Option<Bucket>,
+  max_throttle: Option<Duration>,
+  ready_at: Option<Instant>,
+  throttled_since: Option<Instant>,
+}
+
+impl RateLimiter {
+  pub(crate) fn new(limit: RateLimit) -> Self {
+    let now = Instant::now();
+    Self {
+      bytes: limit.bytes_per_second.map(|rate| Bucket::new(rate, now)),
+      frames: limit.frames_per_second.map(|rate| Bucket::new(rate, now)),
+      max_throttle: limit.max_throttle,
+      ready_at: None,
+      throttled_since: None,
+    }
+  }
+
+  /// Waits until the frames recorded so far are within the limit. Cancel safe, and ready at once when
+  /// `ready_at` has passed.
+  pub(crate) async fn wait(&self) {
+    if let Some(ready_at) = self.ready_at() {
+      tokio::time::sleep_until(ready_at).await;
+    }
+  }
+
+  /// Returns when the frames recorded so far are within the limit, or `None` if they already are.
+  pub(crate) fn ready_at(&self) -> Option<Instant> {
+    self.ready_at.filter(|&ready_at| ready_at > Instant::now())
+  }
+
+  /// Records `frames` frames carrying `bytes` payload bytes. Returns `false` once the connection has been
+  /// over the limit for longer than `max_throttle`.
+  pub(crate) fn record(&mut self, frames: u64, bytes: u64) -> bool {
+    let now = Instant::now();
+    let delay = [
+      self.frames.as_mut().map(|bucket| bucket.take(frames, now)),
+      self.bytes.as_mut().map(|bucket| bucket.take(bytes, now)),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or_default();
+
+    self.ready_at = Some(now + delay);
+    if delay.is_zero() {
+      self.throttled_since = None;
+      return true;
+    }
+    let since = *self.throttled_since.get_or_insert(now);
+    self
+      .max_throttle
+      .is_none_or(|max_throttle| now - since <= max_throttle)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bucket() {
+    let now = Instant::now();
+    let mut bucket = Bucket::new(100, now);
+    assert_eq!(bucket.take(100, now), Duration::ZERO);
+    assert_eq!(bucket.take(50, now), Duration::from_millis(500));
+
+    // Refilled at 100 tokens per second, and never above 100.
+    let later = now + Duration::from_secs(1);
+    assert_eq!(bucket.take(50, later), Duration::ZERO);
+    let much_later = later + Duration::from_secs(10);
+    assert_eq!(bucket.take(100, much_later), Duration::ZERO);
+    assert!(bucket.take(1, much_later) > Duration::ZERO);
+  }
+}
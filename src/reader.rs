@@ -0,0 +1,207 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Buf;
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+use tokio::io::ReadBuf;
+
+use crate::frame::check_utf8;
+use crate::mask::unmask;
+use crate::FrameHeader;
+use crate::OpCode;
+use crate::ReadHalf;
+use crate::Role;
+use crate::WebSocketError;
+
+/// The payload of a frame returned by `WebSocket::read_frame_streaming`.
+///
+/// Reads return exactly the bytes of the payload, then EOF. Data frame payloads are read from the
+/// connection as they are requested, so memory use does not depend on the frame size. If the reader is
+/// dropped before EOF, the rest of the payload is discarded by the next read on the `WebSocket`.
+///
+/// Text frames that end a message are checked to be valid UTF-8 as they are read, failing the read with
+/// an `io::ErrorKind::InvalidData` error wrapping `WebSocketError::InvalidUTF8`.
+pub struct PayloadReader<'a, S> {
+  stream: &'a mut S,
+  read_half: &'a mut ReadHalf,
+  /// The payload of a frame that was read whole.
+  buffered: Option<Bytes>,
+  /// The mask applied to the bytes read, so that they can be unmasked and validated.
+  mask: Option<[u8; 4]>,
+  unmask: bool,
+  validate: bool,
+  offset: usize,
+  /// The start of a character split between two reads.
+  partial: [u8; 4],
+  partial_len: usize,
+}
+
+impl<'a, S> PayloadReader<'a, S> {
+  pub(crate) fn new(
+    stream: &'a mut S,
+    read_half: &'a mut ReadHalf,
+    header: &FrameHeader,
+    mask: Option<[u8; 4]>,
+  ) -> Self {
+    // As with `read_frame`, only frames from the client are unmasked.
+    let unmask = read_half.auto_apply_mask && read_half.role == Role::Server;
+    let validate =
+      read_half.validate_text && header.opcode == OpCode::Text && header.fin;
+    Self {
+      stream,
+      read_half,
+      buffered: None,
+      mask,
+      unmask,
+      validate,
+      offset: 0,
+      partial: [0; 4],
+      partial_len: 0,
+    }
+  }
+
+  pub(crate) fn buffered(
+    stream: &'a mut S,
+    read_half: &'a mut ReadHalf,
+    payload: Bytes,
+  ) -> Self {
+    Self {
+      stream,
+      read_half,
+      buffered: Some(payload),
+      mask: None,
+      unmask: false,
+      validate: false,
+      offset: 0,
+      partial: [0; 4],
+      partial_len: 0,
+    }
+  }
+
+  /// Returns the number of payload bytes left to read.
+  pub fn remaining(&self) -> usize {
+    match &self.buffered {
+      Some(payload) => payload.len(),
+      None => self.read_half.streamed,
+    }
+  }
+
+  /// Unmasks and validates `data`, the next bytes of the payload.
+  fn process(&mut self, data: &mut [u8]) -> Result<(), WebSocketError> {
+    let Some(mut mask) = self.mask.filter(|_| self.unmask || self.validate)
+    else {
+      return self.check_utf8(data);
+    };
+
+    mask.rotate_left(self.offset % 4);
+    self.offset += data.len();
+    unmask(data, mask);
+    let res = self.check_utf8(data);
+    if !self.unmask {
+      unmask(data, mask);
+    }
+    res
+  }
+
+  /// Validates the UTF-8 of the next bytes of a text payload, keeping a character cut at the end for the
+  /// next call.
+  fn check_utf8(&mut self, mut data: &[u8]) -> Result<(), WebSocketError> {
+    if !self.validate {
+      return Ok(());
+    }
+
+    while self.partial_len > 0 && !data.is_empty() {
+      self.partial[self.partial_len] = data[0];
+      self.partial_len += 1;
+      data = &data[1..];
+      match check_utf8(&self.partial[..self.partial_len]) {
+        Ok(()) => self.partial_len = 0,
+        Err(e) if e.error_len.is_none() => {}
+        Err(_) => return Err(WebSocketError::InvalidUTF8),
+      }
+    }
+
+    match check_utf8(data) {
+      Ok(()) => {}
+      Err(e) if e.error_len.is_none() => {
+        let rest = &data[e.valid_up_to..];
+        self.partial[..rest.len()].copy_from_slice(rest);
+        self.partial_len = rest.len();
+      }
+      Err(_) => return Err(WebSocketError::InvalidUTF8),
+    }
+
+    if self.read_half.streamed == 0 && self.partial_len > 0 {
+      return Err(WebSocketError::InvalidUTF8);
+    }
+    Ok(())
+  }
+}
+
+impl<S> AsyncRead for PayloadReader<'_, S>
+where
+  S: AsyncRead + Unpin,
+{
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if let Some(payload) = &mut this.buffered {
+      let n = payload.len().min(buf.remaining());
+      buf.put_slice(&payload.split_to(n));
+      return Poll::Ready(Ok(()));
+    }
+
+    let max = this.read_half.streamed.min(buf.remaining());
+    if max == 0 {
+      return Poll::Ready(Ok(()));
+    }
+
+    // Bytes already buffered with the header come first, the rest is read
+    // straight into `buf`.
+    let start = buf.filled().len();
+    let read_buffer = &mut this.read_half.buffer;
+    if !read_buffer.is_empty() {
+      let n = max.min(read_buffer.len());
+      buf.put_slice(&read_buffer[..n]);
+      read_buffer.advance(n);
+    } else {
+      let mut limited = ReadBuf::new(buf.initialize_unfilled_to(max));
+      ready!(Pin::new(&mut *this.stream).poll_read(cx, &mut limited))?;
+      let n = limited.filled().len();
+      if n == 0 {
+        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+      }
+      buf.advance(n);
+    }
+
+    let data = &mut buf.filled_mut()[start..];
+    this.read_half.streamed -= data.len();
+    if let Err(e) = this.process(data) {
+      // Nothing is returned along with an error.
+      buf.set_filled(start);
+      return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e)));
+    }
+    Poll::Ready(Ok(()))
+  }
+}
@@ -0,0 +1,325 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+
+use crate::Frame;
+use crate::OpCode;
+use crate::Payload;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Delays between connection attempts of a [`ReconnectingWebSocket`].
+///
+/// The delay starts at `initial` and doubles after every failed attempt, up to `max`. With `jitter`, each
+/// delay is picked at random between half and all of it, so that clients dropped together do not reconnect
+/// together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+  /// The delay before the first retry.
+  pub initial: Duration,
+  /// The longest delay between two attempts.
+  pub max: Duration,
+  /// The number of retries after which the last connection error is returned. `None` retries forever.
+  pub max_retries: Option<u32>,
+  /// Whether to randomize the delays.
+  pub jitter: bool,
+}
+
+impl Default for Backoff {
+  fn default() -> Self {
+    Self {
+      initial: Duration::from_millis(100),
+      max: Duration::from_secs(30),
+      max_retries: None,
+      jitter: true,
+    }
+  }
+}
+
+impl Backoff {
+  /// Returns the delay before retry number `retry`, starting at 0.
+  fn delay(&self, retry: u32) -> Duration {
+    let delay = self
+      .initial
+      .saturating_mul(2u32.saturating_pow(retry))
+      .min(self.max);
+    if self.jitter {
+      delay.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+    } else {
+      delay
+    }
+  }
+}
+
+/// What [`ReconnectingWebSocket::read_frame`] returns.
+pub enum ReconnectEvent<'f> {
+  /// A frame was received.
+  Frame(Frame<'f>),
+  /// The connection was lost and a new one established. State tied to the connection, such as
+  /// subscriptions, should be set up again.
+  Reconnected,
+}
+
+/// A client connection that is re-established when it drops.
+///
+/// `connect` is called to open every connection, performing the handshake. When reading or writing fails
+/// because the connection was lost, it is called again with the delays of the [`Backoff`], and the frames
+/// that could not be written are sent over the new connection.
+///
+/// A close frame from the peer is returned like any other frame, the next read or write reconnects. Once
+/// a close frame has been written, the connection is no longer re-established.
+///
+/// A fragmented message interrupted by a reconnect is sent again from its first fragment over the new
+/// connection.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{handshake, Backoff, Frame, ReconnectEvent, ReconnectingWebSocket};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn subscribe() -> Result<()> {
+///   let connect = || async {
+///     let stream = TcpStream::connect("localhost:9001").await?;
+///     let (ws, _) =
+///       handshake::client_raw(stream, "/", "localhost:9001", &[]).await?;
+///     Ok(ws)
+///   };
+///   let mut ws = ReconnectingWebSocket::connect(connect, Backoff::default()).await?;
+///   ws.write_frame(Frame::text(b"subscribe".as_ref().into())).await?;
+///
+///   loop {
+///     match ws.read_frame().await? {
+///       ReconnectEvent::Frame(frame) => println!("{:?}", frame.opcode),
+///       ReconnectEvent::Reconnected => {
+///         ws.write_frame(Frame::text(b"subscribe".as_ref().into())).await?;
+///       }
+///     }
+///   }
+/// }
+/// ```
+pub struct ReconnectingWebSocket<S, F> {
+  ws: Option<WebSocket<S>>,
+  connect: F,
+  backoff: Backoff,
+  queue: VecDeque<Frame<'static>>,
+  /// The fragments written of the message that is not complete yet.
+  open_message: Vec<Frame<'static>>,
+  closed: bool,
+}
+
+impl<S, F, Fut> ReconnectingWebSocket<S, F>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<WebSocket<S>, WebSocketError>>,
+{
+  /// Opens the first connection, retrying as configured by `backoff`.
+  pub async fn connect(
+    connect: F,
+    backoff: Backoff,
+  ) -> Result<Self, WebSocketError> {
+    let mut this = Self {
+      ws: None,
+      connect,
+      backoff,
+      queue: VecDeque::new(),
+      open_message: Vec::new(),
+      closed: false,
+    };
+    this.reconnect().await?;
+    Ok(this)
+  }
+
+  /// Returns the current connection, if it is up.
+  pub fn get_mut(&mut self) -> Option<&mut WebSocket<S>> {
+    self.ws.as_mut()
+  }
+
+  /// Reads a frame from the current connection, reconnecting if it was lost.
+  pub async fn read_frame<'f>(
+    &mut self,
+  ) -> Result<ReconnectEvent<'f>, WebSocketError> {
+    let Some(ws) = self.ws.as_mut() else {
+      self.reconnect().await?;
+      return Ok(ReconnectEvent::Reconnected);
+    };
+
+    match ws.read_frame().await {
+      Ok(frame) => {
+        if frame.opcode == OpCode::Close {
+          self.ws = None;
+        }
+        Ok(ReconnectEvent::Frame(frame))
+      }
+      Err(e) if !self.closed && is_disconnect(&e) => {
+        self.ws = None;
+        self.reconnect().await?;
+        Ok(ReconnectEvent::Reconnected)
+      }
+      Err(e) => {
+        self.ws = None;
+        Err(e)
+      }
+    }
+  }
+
+  /// Writes a frame, reconnecting if the connection was lost. Returns whether a new connection was
+  /// established to send it.
+  ///
+  /// The frame is copied so it can be sent again. It stays queued if reconnecting fails, and is sent
+  /// before any other frame once a connection is up.
+  pub async fn write_frame(
+    &mut self,
+    frame: Frame<'_>,
+  ) -> Result<bool, WebSocketError> {
+    let payload = frame.payload.to_vec().into();
+    self.queue.push_back(copy_frame(&frame, payload));
+
+    if self.ws.is_none() {
+      self.reconnect().await?;
+      return Ok(true);
+    }
+    match self.write_queue().await {
+      Ok(()) => Ok(false),
+      Err(e) if !self.closed && is_disconnect(&e) => {
+        self.reconnect().await?;
+        Ok(true)
+      }
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Opens a new connection and sends the queued frames over it.
+  async fn reconnect(&mut self) -> Result<(), WebSocketError> {
+    if self.closed {
+      return Err(WebSocketError::ConnectionClosed);
+    }
+    self.ws = None;
+
+    let mut retry = 0;
+    loop {
+      let e = match (self.connect)().await {
+        Ok(ws) => {
+          self.ws = Some(ws);
+          // The new connection can not continue a message started on the
+          // previous one.
+          for frame in self.open_message.drain(..).rev() {
+            self.queue.push_front(frame);
+          }
+          match self.write_queue().await {
+            Ok(()) => return Ok(()),
+            Err(e) if is_disconnect(&e) => e,
+            // The connection is up, the frame was the problem.
+            Err(e) => return Err(e),
+          }
+        }
+        Err(e) => e,
+      };
+
+      if self.backoff.max_retries.is_some_and(|max| retry >= max) {
+        self.ws = None;
+        return Err(e);
+      }
+      tokio::time::sleep(self.backoff.delay(retry)).await;
+      retry += 1;
+    }
+  }
+
+  /// Writes the queued frames, removing each one once it has been written.
+  async fn write_queue(&mut self) -> Result<(), WebSocketError> {
+    let ws = self.ws.as_mut().ok_or(WebSocketError::ConnectionClosed)?;
+    while let Some(frame) = self.queue.front() {
+      let payload = frame.payload[..].into();
+      if let Err(e) = ws.write_frame(copy_frame(frame, payload)).await {
+        // Only frames lost with the connection are sent again.
+        if is_disconnect(&e) {
+          self.ws = None;
+        } else {
+          self.queue.pop_front();
+        }
+        return Err(e);
+      }
+
+      let frame = self.queue.pop_front().unwrap();
+      match frame.opcode {
+        OpCode::Close => self.closed = true,
+        opcode if opcode.is_control() => {}
+        _ if frame.fin => self.open_message.clear(),
+        _ => self.open_message.push(frame),
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Copies the header of `frame`, reserved bits included, onto `payload`.
+fn copy_frame<'a>(frame: &Frame<'_>, payload: Payload<'a>) -> Frame<'a> {
+  let mut copy =
+    Frame::new(frame.fin, frame.opcode, None, payload, frame.compressed);
+  copy.rsv2 = frame.rsv2;
+  copy.rsv3 = frame.rsv3;
+  copy
+}
+
+/// Whether `e` means the connection was lost, rather than misused or broken by the peer.
+fn is_disconnect(e: &WebSocketError) -> bool {
+  matches!(
+    e,
+    WebSocketError::IoError(_)
+      | WebSocketError::UnexpectedEOF
+      | WebSocketError::ConnectionReset
+      | WebSocketError::ConnectionClosed
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backoff() {
+    let backoff = Backoff {
+      initial: Duration::from_millis(100),
+      max: Duration::from_secs(1),
+      max_retries: None,
+      jitter: false,
+    };
+    assert_eq!(backoff.delay(0), Duration::from_millis(100));
+    assert_eq!(backoff.delay(3), Duration::from_millis(800));
+    assert_eq!(backoff.delay(4), Duration::from_secs(1));
+    assert_eq!(backoff.delay(100), Duration::from_secs(1));
+
+    let backoff = Backoff {
+      jitter: true,
+      ..backoff
+    };
+    for retry in 0..10 {
+      let delay = backoff.delay(retry);
+      let max = Backoff {
+        jitter: false,
+        ..backoff
+      }
+      .delay(retry);
+      assert!(delay >= max / 2 && delay <= max);
+    }
+  }
+}
@@ -0,0 +1,449 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::pin;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::time::Sleep;
+
+use crate::rate::RateLimiter;
+use crate::Frame;
+use crate::OpCode;
+use crate::ReadHalf;
+use crate::WebSocket;
+use crate::WebSocketError;
+use crate::WriteHalf;
+
+/// A byte stream over the binary messages of a WebSocket connection.
+///
+/// Every write is sent as a single binary frame. Reads return the payloads of the data frames received, in
+/// order and without message boundaries; a close frame from the peer is reported as EOF. Pings are answered
+/// and close frames replied to as configured on the `WebSocket`.
+///
+/// Frames are buffered before being written, call `flush` to push them to the stream. `shutdown` sends a
+/// close frame. The number of pongs waiting to be written is capped, see `set_max_pending_pongs`, so a peer
+/// flooding pings without reading can not grow the write buffer.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::{WebSocket, WebSocketStream};
+/// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+/// use tokio::net::TcpStream;
+/// use anyhow::Result;
+///
+/// async fn tunnel(ws: WebSocket<TcpStream>) -> Result<()> {
+///   let mut stream = WebSocketStream::new(ws);
+///   stream.write_all(b"ping").await?;
+///   stream.flush().await?;
+///
+///   let mut buf = [0; 4];
+///   stream.read_exact(&mut buf).await?;
+///   Ok(())
+/// }
+/// ```
+pub struct WebSocketStream<S> {
+  stream: S,
+  read_half: ReadHalf,
+  write_half: WriteHalf,
+  read_buf: Vec<u8>,
+  read_pos: usize,
+  write_buf: Vec<u8>,
+  write_pos: usize,
+  pending_pongs: VecDeque<Frame<'static>>,
+  max_pending_pongs: usize,
+  pong_overflow: PongOverflow,
+  throttle: Option<Pin<Box<Sleep>>>,
+  eof: bool,
+}
+
+impl<S> WebSocketStream<S> {
+  /// Wraps a `WebSocket` whose handshake has completed.
+  pub fn new(ws: WebSocket<S>) -> Self {
+    let (stream, read_half, write_half) = ws.into_parts_internal();
+    Self {
+      stream,
+      read_half,
+      write_half,
+      read_buf: Vec::new(),
+      read_pos: 0,
+      write_buf: Vec::new(),
+      write_pos: 0,
+      pending_pongs: VecDeque::new(),
+      max_pending_pongs: 1,
+      pong_overflow: PongOverflow::DropOldest,
+      throttle: None,
+      eof: false,
+    }
+  }
+
+  /// Sets how many pongs can wait to be written, and what to do with a ping received when that many are
+  /// already waiting.
+  ///
+  /// By default one pong waits and a newer one replaces it: only the most recent ping is answered. The cap
+  /// is at least one.
+  pub fn set_max_pending_pongs(&mut self, max: usize, overflow: PongOverflow) {
+    self.max_pending_pongs = max.max(1);
+    self.pong_overflow = overflow;
+  }
+
+  /// Consumes the `WebSocketStream` and returns the underlying stream. Data that was not flushed is lost.
+  pub fn into_inner(self) -> S {
+    self.stream
+  }
+}
+
+impl<S> WebSocketStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  /// Writes out the frames buffered so far, then the pending pongs.
+  fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    loop {
+      while self.write_pos < self.write_buf.len() {
+        let n = ready!(Pin::new(&mut self.stream)
+          .poll_write(cx, &self.write_buf[self.write_pos..]))?;
+        if n == 0 {
+          return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+        }
+        self.write_pos += n;
+      }
+      self.write_buf.clear();
+      self.write_pos = 0;
+
+      match self.pending_pongs.pop_front() {
+        Some(pong) if !self.write_half.closed => self.queue_frame(pong)?,
+        _ => return Poll::Ready(Ok(())),
+      }
+    }
+  }
+
+  fn queue_frame(&mut self, frame: Frame<'_>) -> io::Result<()> {
+    self
+      .write_half
+      .encode_frame(frame, &mut self.write_buf)
+      .map_err(into_io_error)
+  }
+
+  /// Reads the next complete frame from the read buffer, filling it from the stream as needed. Returns
+  /// `None` on EOF.
+  fn poll_next_frame(
+    &mut self,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<Option<Frame<'static>>>> {
+    loop {
+      while !self.read_half.has_buffered_frame() {
+        let mut chunk = [0; 8192];
+        let mut buf = ReadBuf::new(&mut chunk);
+        ready!(Pin::new(&mut self.stream).poll_read(cx, &mut buf))?;
+        if buf.filled().is_empty() {
+          return Poll::Ready(if self.read_half.buffer.is_empty() {
+            Ok(None)
+          } else {
+            Err(into_io_error(WebSocketError::UnexpectedEOF))
+          });
+        }
+        self.read_half.buffer.extend_from_slice(buf.filled());
+      }
+
+      // Wait for the read rate limit here, so that reading the frame below
+      // does not have to.
+      let ready_at = self
+        .read_half
+        .rate_limiter
+        .as_ref()
+        .and_then(RateLimiter::ready_at);
+      if let Some(ready_at) = ready_at {
+        let throttle = self
+          .throttle
+          .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(ready_at)));
+        if throttle.deadline() != ready_at {
+          throttle.as_mut().reset(ready_at);
+        }
+        ready!(throttle.as_mut().poll(cx));
+      }
+
+      // The frame is buffered, reading it never touches the stream.
+      let mut empty = tokio::io::empty();
+      let read = pin!(self.read_half.read_frame_inner(&mut empty)).poll(cx);
+      let Poll::Ready((res, obligated_send)) = read else {
+        unreachable!("buffered frame read from the stream");
+      };
+
+      if let Some(frame) = obligated_send {
+        if frame.opcode == OpCode::Pong {
+          if self.pending_pongs.len() >= self.max_pending_pongs {
+            match self.pong_overflow {
+              PongOverflow::DropOldest => {
+                self.pending_pongs.pop_front();
+              }
+              PongOverflow::Error => {
+                let e = WebSocketError::TooManyPendingPongs;
+                return Poll::Ready(Err(into_io_error(e)));
+              }
+            }
+          }
+          self.pending_pongs.push_back(frame);
+        } else if !self.write_half.closed {
+          self.queue_frame(frame)?;
+        }
+      }
+      if let Some(frame) = res.map_err(into_io_error)? {
+        return Poll::Ready(Ok(Some(frame)));
+      }
+    }
+  }
+}
+
+/// What `WebSocketStream` does with a pong over the cap, see `WebSocketStream::set_max_pending_pongs`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PongOverflow {
+  /// Drop the oldest pending pong, only the most recent pings are answered.
+  DropOldest,
+  /// Fail the read with `WebSocketError::TooManyPendingPongs`.
+  Error,
+}
+
+fn into_io_error(e: WebSocketError) -> io::Error {
+  match e {
+    WebSocketError::IoError(e) => e,
+    e => io::Error::new(io::ErrorKind::InvalidData, e),
+  }
+}
+
+impl<S> AsyncRead for WebSocketStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    loop {
+      // Pongs and close replies go out as soon as possible. Waiting for the
+      // write is not needed to make progress on the read side.
+      if let Poll::Ready(Err(e)) = this.poll_write_buf(cx) {
+        return Poll::Ready(Err(e));
+      }
+
+      if this.read_pos < this.read_buf.len() {
+        let n = buf.remaining().min(this.read_buf.len() - this.read_pos);
+        buf.put_slice(&this.read_buf[this.read_pos..this.read_pos + n]);
+        this.read_pos += n;
+        return Poll::Ready(Ok(()));
+      }
+
+      if this.eof {
+        return Poll::Ready(Ok(()));
+      }
+
+      let Some(frame) = ready!(this.poll_next_frame(cx))? else {
+        this.eof = true;
+        continue;
+      };
+      match frame.opcode {
+        OpCode::Binary | OpCode::Text | OpCode::Continuation => {
+          this.read_buf = frame.payload.into();
+          this.read_pos = 0;
+        }
+        OpCode::Close => this.eof = true,
+        OpCode::Ping | OpCode::Pong | OpCode::Other(_) => {}
+      }
+    }
+  }
+}
+
+impl<S> AsyncWrite for WebSocketStream<S>
+where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let this = self.get_mut();
+    ready!(this.poll_write_buf(cx))?;
+    this.queue_frame(Frame::binary(buf.into()))?;
+    // The frame is buffered, it is written on the next call or on flush.
+    let _ = this.poll_write_buf(cx)?;
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    ready!(this.poll_write_buf(cx))?;
+    Pin::new(&mut this.stream).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if !this.write_half.closed {
+      this.queue_frame(Frame::close(1000, &[]))?;
+    }
+    ready!(this.poll_write_buf(cx))?;
+    Pin::new(&mut this.stream).poll_shutdown(cx)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use tokio::io::AsyncReadExt;
+
+  use crate::Role;
+
+  #[tokio::test]
+  async fn ping_flood() {
+    // Small enough for the pongs to fill the pipe to the client, which never
+    // reads them.
+    let (client, server) = tokio::io::duplex(16);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut stream =
+      WebSocketStream::new(WebSocket::after_handshake(server, Role::Server));
+
+    // The client is handed back so the pipe stays open.
+    let task = tokio::spawn(async move {
+      for i in 0..100 {
+        let payload = format!("p{i:03}").into_bytes();
+        let ping = Frame::new(true, OpCode::Ping, None, payload.into(), false);
+        client.write_frame(ping).await.unwrap();
+      }
+      client
+        .write_frame(Frame::binary(b"end".as_ref().into()))
+        .await
+        .unwrap();
+      client
+    });
+
+    let mut buf = [0; 3];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"end");
+    let _client = task.await.unwrap();
+
+    // At most one pong is being written, the others were replaced by the
+    // answer to the last ping.
+    assert!(stream.write_buf.len() <= 6);
+    assert!(stream.pending_pongs.len() <= 1);
+    match stream.pending_pongs.back() {
+      Some(pong) => assert_eq!(&pong.payload[..], b"p099"),
+      None => assert!(stream.write_buf.ends_with(b"p099")),
+    }
+  }
+
+  #[tokio::test]
+  async fn max_pending_pongs() {
+    let (client, server) = tokio::io::duplex(16);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut stream =
+      WebSocketStream::new(WebSocket::after_handshake(server, Role::Server));
+    stream.set_max_pending_pongs(3, PongOverflow::DropOldest);
+
+    let task = tokio::spawn(async move {
+      for i in 0..100 {
+        let payload = format!("p{i:03}").into_bytes();
+        let ping = Frame::new(true, OpCode::Ping, None, payload.into(), false);
+        client.write_frame(ping).await.unwrap();
+      }
+      client
+        .write_frame(Frame::binary(b"end".as_ref().into()))
+        .await
+        .unwrap();
+      client
+    });
+
+    let mut buf = [0; 3];
+    stream.read_exact(&mut buf).await.unwrap();
+    let _client = task.await.unwrap();
+
+    // The oldest pongs were dropped, the last three pings are answered.
+    let pending: Vec<_> = stream
+      .pending_pongs
+      .iter()
+      .map(|pong| &pong.payload[..])
+      .collect();
+    assert_eq!(pending, [b"p097", b"p098", b"p099"]);
+  }
+
+  #[tokio::test]
+  async fn pending_pongs_overflow_error() {
+    let (client, server) = tokio::io::duplex(16);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut stream =
+      WebSocketStream::new(WebSocket::after_handshake(server, Role::Server));
+    stream.set_max_pending_pongs(1, PongOverflow::Error);
+
+    // The client never reads its pongs, nor stops writing pings.
+    let task = tokio::spawn(async move {
+      loop {
+        let ping =
+          Frame::new(true, OpCode::Ping, None, b"ping".to_vec().into(), false);
+        if client.write_frame(ping).await.is_err() {
+          break;
+        }
+      }
+    });
+
+    let mut buf = [0; 1];
+    let err = stream.read(&mut buf).await.unwrap_err();
+    assert!(matches!(
+      err.into_inner().unwrap().downcast_ref(),
+      Some(WebSocketError::TooManyPendingPongs)
+    ));
+    task.abort();
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn read_rate_limit() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = WebSocket::after_handshake(client, Role::Client);
+    let mut server = WebSocket::after_handshake(server, Role::Server);
+    server.set_read_rate_limit(Some(crate::RateLimit {
+      frames_per_second: Some(10),
+      ..Default::default()
+    }));
+    let mut stream = WebSocketStream::new(server);
+
+    for _ in 0..13 {
+      client
+        .write_frame(Frame::binary(b"x".as_ref().into()))
+        .await
+        .unwrap();
+    }
+
+    // A burst of 10 frames goes through, the next ones at 10 per second.
+    let start = tokio::time::Instant::now();
+    let mut buf = [0; 13];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+  }
+}
@@ -0,0 +1,237 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::DuplexStream;
+use tokio::io::ReadBuf;
+
+use crate::Frame;
+use crate::Role;
+use crate::WebSocket;
+use crate::WebSocketError;
+
+/// Creates a connected client/server `WebSocket` pair over an in-memory duplex stream.
+///
+/// No handshake is performed, both ends behave as if it had already completed.
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::testing;
+/// use fastwebsockets::Frame;
+/// use anyhow::Result;
+///
+/// async fn echo() -> Result<()> {
+///   let (mut client, mut server) = testing::pair();
+///   client.write_frame(Frame::text(b"hello".as_ref().into())).await?;
+///   let frame = server.read_frame().await?;
+///   assert_eq!(&frame.payload[..], b"hello");
+///   Ok(())
+/// }
+/// ```
+pub fn pair() -> (WebSocket<DuplexStream>, WebSocket<DuplexStream>) {
+  pair_with_capacity(64 * 1024)
+}
+
+/// Same as [`pair`], with the maximum number of bytes buffered in each direction before writes wait.
+pub fn pair_with_capacity(
+  max_buf_size: usize,
+) -> (WebSocket<DuplexStream>, WebSocket<DuplexStream>) {
+  let (client, server) = tokio::io::duplex(max_buf_size);
+  (
+    WebSocket::after_handshake(client, Role::Client),
+    WebSocket::after_handshake(server, Role::Server),
+  )
+}
+
+/// A stream that records every byte written to it, to assert the exact wire output.
+///
+/// Reads are forwarded to the inner stream.
+pub struct RecordingStream<S> {
+  inner: S,
+  written: Vec<u8>,
+}
+
+impl<S> RecordingStream<S> {
+  /// Wraps `inner`, recording the bytes written through it.
+  pub fn new(inner: S) -> Self {
+    Self {
+      inner,
+      written: Vec::new(),
+    }
+  }
+
+  /// Returns the bytes written so far.
+  pub fn written(&self) -> &[u8] {
+    &self.written
+  }
+
+  /// Returns the bytes written so far and clears the record.
+  pub fn take_written(&mut self) -> Vec<u8> {
+    std::mem::take(&mut self.written)
+  }
+
+  /// Consumes the `RecordingStream` and returns the inner stream.
+  pub fn into_inner(self) -> S {
+    self.inner
+  }
+}
+
+impl<S> AsyncRead for RecordingStream<S>
+where
+  S: AsyncRead + Unpin,
+{
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+  }
+}
+
+impl<S> AsyncWrite for RecordingStream<S>
+where
+  S: AsyncWrite + Unpin,
+{
+  fn poll_write(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<std::io::Result<usize>> {
+    let this = self.get_mut();
+    let res = Pin::new(&mut this.inner).poll_write(cx, buf);
+    if let Poll::Ready(Ok(n)) = res {
+      this.written.extend_from_slice(&buf[..n]);
+    }
+    res
+  }
+
+  fn poll_write_vectored(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    bufs: &[IoSlice<'_>],
+  ) -> Poll<std::io::Result<usize>> {
+    let this = self.get_mut();
+    let res = Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+    if let Poll::Ready(Ok(mut n)) = res {
+      for buf in bufs {
+        let len = buf.len().min(n);
+        this.written.extend_from_slice(&buf[..len]);
+        n -= len;
+      }
+    }
+    res
+  }
+
+  fn is_write_vectored(&self) -> bool {
+    self.inner.is_write_vectored()
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+  }
+}
+
+/// Decodes `data` as frames received by a server, as a fuzz target would.
+///
+/// Returns the frames read and the error that stopped decoding, `WebSocketError::UnexpectedEOF` once `data`
+/// is exhausted. Replies such as pongs are discarded. It must never panic, whatever the input.
+///
+/// # Example
+///
+/// ```ignore
+/// #![no_main]
+///
+/// use libfuzzer_sys::fuzz_target;
+///
+/// fuzz_target!(|data: &[u8]| {
+///   fastwebsockets::testing::fuzz_decode(data);
+/// });
+/// ```
+pub fn fuzz_decode(data: &[u8]) -> (Vec<Frame<'static>>, WebSocketError) {
+  let mut ws = WebSocket::after_handshake(SliceStream(data), Role::Server);
+  ws.set_writev(false);
+
+  let mut frames = Vec::new();
+  let mut cx = Context::from_waker(std::task::Waker::noop());
+  loop {
+    // Reads from a `SliceStream` are always ready, so neither is the future.
+    let read = std::pin::pin!(ws.read_frame());
+    match read.poll(&mut cx) {
+      Poll::Ready(Ok(frame)) => frames.push(frame),
+      Poll::Ready(Err(e)) => return (frames, e),
+      Poll::Pending => return (frames, WebSocketError::UnexpectedEOF),
+    }
+  }
+}
+
+/// Reads from a slice until it is exhausted, discards writes.
+struct SliceStream<'a>(&'a [u8]);
+
+impl AsyncRead for SliceStream<'_> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    let this = self.get_mut();
+    let len = buf.remaining().min(this.0.len());
+    let (read, rest) = this.0.split_at(len);
+    buf.put_slice(read);
+    this.0 = rest;
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncWrite for SliceStream<'_> {
+  fn poll_write(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<std::io::Result<usize>> {
+    Poll::Ready(Ok(buf.len()))
+  }
+
+  fn poll_flush(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_shutdown(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+}
@@ -18,33 +18,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use base64;
-use base64::engine::general_purpose::STANDARD;
-use base64::Engine;
 use http_body_util::Empty;
 use hyper::body::Bytes;
 use hyper::Request;
 use hyper::Response;
 use hyper_util::rt::TokioIo;
 use pin_project::pin_project;
-use sha1::Digest;
-use sha1::Sha1;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
 
+use crate::handshake::accept_key;
 use crate::Role;
 use crate::WebSocket;
 use crate::WebSocketError;
 
-fn sec_websocket_protocol(key: &[u8]) -> String {
-  let mut sha1 = Sha1::new();
-  sha1.update(key);
-  sha1.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11"); // magic string
-  let result = sha1.finalize();
-  STANDARD.encode(&result[..])
-}
-
 type Error = WebSocketError;
 
 pub struct IncomingUpgrade {
@@ -64,6 +52,7 @@ impl IncomingUpgrade {
 
     let stream = UpgradeFut {
       inner: self.on_upgrade,
+      protocol: None,
     };
 
     Ok((response, stream))
@@ -100,17 +89,69 @@ where
       .ok_or(hyper::StatusCode::BAD_REQUEST)?;
     Ok(Self {
       on_upgrade,
-      key: sec_websocket_protocol(key.as_bytes()),
+      key: accept_key(key.as_bytes()),
     })
   }
 }
 
 /// A future that resolves to a websocket stream when the associated HTTP upgrade completes.
+///
+/// Dropping it is safe: if the upgrade completed, the upgraded connection is dropped and closed along with it.
 #[pin_project]
 #[derive(Debug)]
 pub struct UpgradeFut {
   #[pin]
   inner: hyper::upgrade::OnUpgrade,
+  protocol: Option<String>,
+}
+
+impl UpgradeFut {
+  /// Waits for the upgrade, giving up when `cancel` completes first, in which case `Ok(None)` is returned.
+  ///
+  /// Use this to drain pending upgrades on shutdown.
+  ///
+  /// # Example
+  ///
+  /// ```
+  /// use fastwebsockets::upgrade::UpgradeFut;
+  /// use tokio::sync::watch;
+  /// use anyhow::Result;
+  ///
+  /// async fn handle(
+  ///   fut: UpgradeFut,
+  ///   mut shutdown: watch::Receiver<bool>,
+  /// ) -> Result<()> {
+  ///   let cancel = async move {
+  ///     let _ = shutdown.wait_for(|&shutdown| shutdown).await;
+  ///   };
+  ///   let Some(mut ws) = fut.cancel_on(cancel).await? else {
+  ///     return Ok(());
+  ///   };
+  ///   let frame = ws.read_frame().await?;
+  ///   Ok(())
+  /// }
+  /// ```
+  pub async fn cancel_on<F>(
+    self,
+    cancel: F,
+  ) -> Result<Option<WebSocket<TokioIo<hyper::upgrade::Upgraded>>>, Error>
+  where
+    F: std::future::Future<Output = ()>,
+  {
+    let mut upgrade = std::pin::pin!(self);
+    let mut cancel = std::pin::pin!(cancel);
+    std::future::poll_fn(|cx| {
+      if let Poll::Ready(res) = std::future::Future::poll(upgrade.as_mut(), cx)
+      {
+        return Poll::Ready(res.map(Some));
+      }
+      if cancel.as_mut().poll(cx).is_ready() {
+        return Poll::Ready(Ok(None));
+      }
+      Poll::Pending
+    })
+    .await
+  }
 }
 
 /// Try to upgrade a received `hyper::Request` to a websocket connection.
@@ -149,20 +190,162 @@ pub fn upgrade<B>(
     .status(hyper::StatusCode::SWITCHING_PROTOCOLS)
     .header(hyper::header::CONNECTION, "upgrade")
     .header(hyper::header::UPGRADE, "websocket")
-    .header(
-      "Sec-WebSocket-Accept",
-      &sec_websocket_protocol(key.as_bytes()),
-    )
+    .header("Sec-WebSocket-Accept", &accept_key(key.as_bytes()))
     .body(Empty::new())
     .expect("bug: failed to build response");
 
   let stream = UpgradeFut {
     inner: hyper::upgrade::on(request),
+    protocol: None,
   };
 
   Ok((response, stream))
 }
 
+/// Like [`upgrade`], but also checks the `Origin` header of the request against `allowed_origins`.
+///
+/// Browsers always send the origin of the page opening the connection, so this protects the endpoint from
+/// cross-site requests. Requests without an `Origin` header, or with one not in the list, are rejected with
+/// `WebSocketError::InvalidOrigin`, which should be answered with `403 Forbidden`. Origins are compared
+/// ASCII case-insensitively, for example `"https://example.com"`.
+pub fn upgrade_with_origin<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  allowed_origins: &[&str],
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  let origin = request
+    .borrow()
+    .headers()
+    .get(hyper::header::ORIGIN)
+    .ok_or(WebSocketError::InvalidOrigin)?;
+  if !allowed_origins
+    .iter()
+    .any(|allowed| allowed.as_bytes().eq_ignore_ascii_case(origin.as_bytes()))
+  {
+    return Err(WebSocketError::InvalidOrigin);
+  }
+  upgrade(request.borrow_mut())
+}
+
+/// Like [`upgrade`], but also selects `selected_protocol` as the subprotocol of the connection.
+///
+/// The protocol is written verbatim in the `Sec-WebSocket-Protocol` header of the response if the client
+/// listed it in its own `Sec-WebSocket-Protocol` header, compared case-sensitively as the RFC requires.
+/// Otherwise, or if it is `None`, the header is left out and the connection has no subprotocol, which the
+/// client can then refuse. The upgraded `WebSocket` returns the selection from `selected_protocol`, as do
+/// clients made with [`crate::handshake::client`].
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::upgrade_with_protocol;
+/// use http_body_util::Empty;
+/// use hyper::body::{Bytes, Incoming};
+/// use hyper::{Request, Response};
+///
+/// fn handle(
+///   request: Request<Incoming>,
+/// ) -> Result<Response<Empty<Bytes>>, fastwebsockets::WebSocketError> {
+///   let (response, fut) =
+///     upgrade_with_protocol(request, Some("graphql-transport-ws"))?;
+///   tokio::spawn(async move {
+///     let ws = fut.await;
+///   });
+///   Ok(response)
+/// }
+/// ```
+pub fn upgrade_with_protocol<B>(
+  mut request: impl std::borrow::BorrowMut<Request<B>>,
+  selected_protocol: Option<&str>,
+) -> Result<(Response<Empty<Bytes>>, UpgradeFut), Error> {
+  let offered = selected_protocol.filter(|protocol| {
+    offered_protocols(request.borrow())
+      .any(|offered| offered == protocol.as_bytes())
+  });
+  let (mut response, mut fut) = upgrade(request.borrow_mut())?;
+  if let Some(protocol) = offered {
+    let value = hyper::header::HeaderValue::from_str(protocol)
+      .map_err(|_| WebSocketError::InvalidValue)?;
+    response
+      .headers_mut()
+      .insert(hyper::header::SEC_WEBSOCKET_PROTOCOL, value);
+    fut.protocol = Some(protocol.to_string());
+  }
+  Ok((response, fut))
+}
+
+/// Whose order of preference decides the subprotocol when the client offers several that the server supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolSelection {
+  /// The first protocol listed by the client that the server supports wins.
+  ClientPreference,
+  /// The first protocol in the server's list that the client offered wins.
+  ServerPreference,
+}
+
+/// Chooses the subprotocol of the connection among the ones offered in the `Sec-WebSocket-Protocol`
+/// headers of `request` and the ones in `supported`, compared case-sensitively. Returns `None` if there is
+/// none in common.
+///
+/// The RFC leaves the choice to the server. Clients usually list protocols by preference, which
+/// `ProtocolSelection::ClientPreference` honors. Pass the result to [`upgrade_with_protocol`].
+///
+/// # Example
+///
+/// ```
+/// use fastwebsockets::upgrade::{
+///   select_protocol, upgrade_with_protocol, ProtocolSelection,
+/// };
+/// use http_body_util::Empty;
+/// use hyper::body::{Bytes, Incoming};
+/// use hyper::{Request, Response};
+///
+/// fn handle(
+///   request: Request<Incoming>,
+/// ) -> Result<Response<Empty<Bytes>>, fastwebsockets::WebSocketError> {
+///   let protocol = select_protocol(
+///     &request,
+///     &["graphql-transport-ws", "graphql-ws"],
+///     ProtocolSelection::ClientPreference,
+///   );
+///   let (response, fut) = upgrade_with_protocol(request, protocol)?;
+///   tokio::spawn(async move {
+///     let ws = fut.await;
+///   });
+///   Ok(response)
+/// }
+/// ```
+pub fn select_protocol<'p, B>(
+  request: &Request<B>,
+  supported: &[&'p str],
+  selection: ProtocolSelection,
+) -> Option<&'p str> {
+  match selection {
+    ProtocolSelection::ClientPreference => {
+      offered_protocols(request).find_map(|offered| {
+        supported
+          .iter()
+          .copied()
+          .find(|protocol| protocol.as_bytes() == offered)
+      })
+    }
+    ProtocolSelection::ServerPreference => {
+      supported.iter().copied().find(|protocol| {
+        offered_protocols(request).any(|offered| offered == protocol.as_bytes())
+      })
+    }
+  }
+}
+
+/// Iterates over the protocols listed in the `Sec-WebSocket-Protocol` headers, in order.
+fn offered_protocols<B>(request: &Request<B>) -> impl Iterator<Item = &[u8]> {
+  request
+    .headers()
+    .get_all(hyper::header::SEC_WEBSOCKET_PROTOCOL)
+    .iter()
+    .flat_map(|value| value.as_bytes().split(|&c| c == b','))
+    .map(trim)
+}
+
 /// Check if a request is a websocket upgrade request.
 ///
 /// If the `Upgrade` header lists multiple protocols,
@@ -226,9 +409,90 @@ impl std::future::Future for UpgradeFut {
       Poll::Pending => return Poll::Pending,
       Poll::Ready(x) => x,
     };
-    Poll::Ready(Ok(WebSocket::after_handshake(
-      TokioIo::new(upgraded?),
-      Role::Server,
-    )))
+    let mut ws =
+      WebSocket::after_handshake(TokioIo::new(upgraded?), Role::Server);
+    ws.protocol = this.protocol.take();
+    Poll::Ready(Ok(ws))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(origin: Option<&str>) -> Request<Empty<Bytes>> {
+    let mut request = Request::builder()
+      .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+      .header("Sec-WebSocket-Version", "13");
+    if let Some(origin) = origin {
+      request = request.header("Origin", origin);
+    }
+    request.body(Empty::new()).unwrap()
+  }
+
+  #[test]
+  fn protocol() {
+    let mut offer = request(None);
+    offer.headers_mut().insert(
+      "Sec-WebSocket-Protocol",
+      "graphql-ws, graphql-transport-ws".parse().unwrap(),
+    );
+    let (response, fut) =
+      upgrade_with_protocol(offer, Some("graphql-transport-ws")).unwrap();
+    assert_eq!(
+      response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+      "graphql-transport-ws"
+    );
+    assert_eq!(fut.protocol.as_deref(), Some("graphql-transport-ws"));
+
+    for selected in [Some("chat"), None] {
+      let (response, _) =
+        upgrade_with_protocol(request(None), selected).unwrap();
+      assert!(response.headers().get("Sec-WebSocket-Protocol").is_none());
+    }
+  }
+
+  #[test]
+  fn protocol_selection() {
+    let mut offer = request(None);
+    offer
+      .headers_mut()
+      .append("Sec-WebSocket-Protocol", "mqtt, wamp".parse().unwrap());
+    offer
+      .headers_mut()
+      .append("Sec-WebSocket-Protocol", "chat".parse().unwrap());
+    let supported = ["chat", "wamp", "v2.stomp"];
+
+    assert_eq!(
+      select_protocol(&offer, &supported, ProtocolSelection::ClientPreference),
+      Some("wamp")
+    );
+    assert_eq!(
+      select_protocol(&offer, &supported, ProtocolSelection::ServerPreference),
+      Some("chat")
+    );
+    for selection in [
+      ProtocolSelection::ClientPreference,
+      ProtocolSelection::ServerPreference,
+    ] {
+      assert_eq!(select_protocol(&offer, &["WAMP"], selection), None);
+      assert_eq!(select_protocol(&request(None), &supported, selection), None);
+    }
+  }
+
+  #[test]
+  fn origin() {
+    let allowed = ["https://example.com"];
+    let (response, _) =
+      upgrade_with_origin(request(Some("https://EXAMPLE.com")), &allowed)
+        .unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::SWITCHING_PROTOCOLS);
+
+    for origin in [None, Some("https://evil.com")] {
+      assert!(matches!(
+        upgrade_with_origin(request(origin), &allowed),
+        Err(WebSocketError::InvalidOrigin)
+      ));
+    }
   }
 }
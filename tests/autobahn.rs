@@ -0,0 +1,192 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Runs the Autobahn|TestSuite fuzzingclient against an in-process echo server.
+//
+// Requires docker, so it is ignored by default:
+//
+//   cargo test --features upgrade --test autobahn -- --ignored
+
+use anyhow::Result;
+use fastwebsockets::upgrade;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketError;
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper::Response;
+use hyper_util::rt::TokioIo;
+use std::path::Path;
+use std::process::Command;
+use tokio::net::TcpListener;
+
+const AUTOBAHN_TESTSUITE_DOCKER: &str = "crossbario/autobahn-testsuite:0.8.2@sha256:5d4ba3aa7d6ab2fdbf6606f3f4ecbe4b66f205ce1cbc176d6cdf650157e52242";
+
+// Outcomes that are not considered a failure.
+const PASSING: &[&str] =
+  &["OK", "INFORMATIONAL", "NON-STRICT", "UNIMPLEMENTED"];
+
+async fn handle_client(fut: upgrade::UpgradeFut) -> Result<(), WebSocketError> {
+  let mut ws = FragmentCollector::new(fut.await?);
+
+  loop {
+    let frame = ws.read_frame().await?;
+    match frame.opcode {
+      OpCode::Close => break,
+      OpCode::Text | OpCode::Binary => {
+        ws.write_frame(frame).await?;
+      }
+      _ => {}
+    }
+  }
+
+  Ok(())
+}
+
+async fn server_upgrade(
+  mut req: Request<Incoming>,
+) -> Result<Response<Empty<Bytes>>, WebSocketError> {
+  let (response, fut) = upgrade::upgrade(&mut req)?;
+
+  tokio::spawn(async move {
+    // Protocol errors are expected, that is what the suite is testing.
+    let _ = handle_client(fut).await;
+  });
+
+  Ok(response)
+}
+
+/// Returns the `behavior` of every case in an Autobahn `index.json` report.
+fn behaviors(report: &str) -> Vec<(String, String)> {
+  const CASE: &str = "\"behavior\": \"";
+
+  let mut results = Vec::new();
+  let mut rest = report;
+  while let Some(start) = rest.find(CASE) {
+    // The case id is the last key opened before its results object.
+    let case = rest[..start]
+      .rsplit("\": {")
+      .nth(1)
+      .and_then(|s| s.rsplit('"').next())
+      .unwrap_or("?")
+      .to_string();
+
+    rest = &rest[start + CASE.len()..];
+    let end = rest.find('"').unwrap();
+    results.push((case, rest[..end].to_string()));
+    rest = &rest[end..];
+  }
+  results
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "requires docker"]
+async fn autobahn_server() -> Result<()> {
+  let listener = TcpListener::bind("127.0.0.1:8080").await?;
+  tokio::spawn(async move {
+    loop {
+      let (stream, _) = listener.accept().await.unwrap();
+      tokio::spawn(async move {
+        let io = TokioIo::new(stream);
+        let _ = http1::Builder::new()
+          .serve_connection(io, service_fn(server_upgrade))
+          .with_upgrades()
+          .await;
+      });
+    }
+  });
+
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("autobahn");
+  let status = tokio::task::spawn_blocking({
+    let dir = dir.clone();
+    move || {
+      Command::new("docker")
+        .current_dir(&dir)
+        .args(["run", "--rm", "--net=host"])
+        .arg("-v")
+        .arg(format!(
+          "{}:/fuzzingclient.json:ro",
+          dir.join("fuzzingclient.json").display()
+        ))
+        .arg("-v")
+        .arg(format!("{}:/reports", dir.join("reports").display()))
+        .arg(AUTOBAHN_TESTSUITE_DOCKER)
+        .args(["wstest", "-m", "fuzzingclient", "-s", "fuzzingclient.json"])
+        .status()
+    }
+  })
+  .await??;
+  assert!(status.success(), "wstest exited with {status}");
+
+  let report = std::fs::read_to_string(dir.join("reports/servers/index.json"))?;
+  let results = behaviors(&report);
+  assert!(!results.is_empty(), "no cases in the report");
+
+  let failed: Vec<_> = results
+    .iter()
+    .filter(|(_, behavior)| !PASSING.contains(&behavior.as_str()))
+    .collect();
+  assert!(
+    failed.is_empty(),
+    "{} / {} cases failed: {:?}",
+    failed.len(),
+    results.len(),
+    failed
+  );
+
+  Ok(())
+}
+
+#[test]
+fn parse_report() {
+  let report = r#"{
+   "fastwebsockets": {
+      "1.1.1": {
+         "behavior": "OK",
+         "behaviorClose": "OK",
+         "duration": 2,
+         "remoteCloseCode": 1000,
+         "reportfile": "fastwebsockets_case_1_1_1.json"
+      },
+      "6.4.3": {
+         "behavior": "NON-STRICT",
+         "behaviorClose": "OK",
+         "duration": 2003,
+         "remoteCloseCode": 1007,
+         "reportfile": "fastwebsockets_case_6_4_3.json"
+      },
+      "7.5.1": {
+         "behavior": "FAILED",
+         "behaviorClose": "FAILED",
+         "duration": 1,
+         "remoteCloseCode": null,
+         "reportfile": "fastwebsockets_case_7_5_1.json"
+      }
+   }
+}"#;
+
+  let results = behaviors(report);
+  assert_eq!(
+    results,
+    vec![
+      ("1.1.1".to_string(), "OK".to_string()),
+      ("6.4.3".to_string(), "NON-STRICT".to_string()),
+      ("7.5.1".to_string(), "FAILED".to_string()),
+    ]
+  );
+}
@@ -0,0 +1,247 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::CloseCode;
+use fastwebsockets::CloseGuard;
+use fastwebsockets::CloseReply;
+use fastwebsockets::CloseState;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketError;
+use std::time::Duration;
+
+#[tokio::test]
+async fn close_state() {
+  let (mut client, mut server) = testing::pair();
+  assert_eq!(client.close_state(), CloseState::Open);
+
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+  assert!(client.is_closed());
+  assert_eq!(client.close_state(), CloseState::ClosingSent);
+
+  server.set_auto_close(false);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(server.close_state(), CloseState::ClosingReceived);
+
+  server.write_frame(Frame::close(1000, b"")).await.unwrap();
+  assert_eq!(server.close_state(), CloseState::Closed);
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(client.close_state(), CloseState::Closed);
+}
+
+#[tokio::test]
+async fn simultaneous_close() {
+  let (mut client, mut server) = testing::pair();
+
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+  server.write_frame(Frame::close(1001, b"")).await.unwrap();
+  // A second close frame is not sent.
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+  assert_eq!(client.write_stats().bytes_written, 2 + 4 + 2);
+
+  // Each side reads the other's close frame without replying to it.
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1001u16.to_be_bytes());
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1000u16.to_be_bytes());
+
+  for ws in [&mut client, &mut server] {
+    assert_eq!(ws.close_state(), CloseState::Closed);
+    assert!(matches!(
+      ws.read_frame().await,
+      Err(WebSocketError::ConnectionClosed)
+    ));
+  }
+}
+
+#[tokio::test]
+async fn read_after_close_sent() {
+  let (mut client, mut server) = testing::pair();
+  server.set_auto_close(false);
+
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+  server
+    .write_frame(Frame::text(b"in flight".as_ref().into()))
+    .await
+    .unwrap();
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"in flight");
+  assert_eq!(client.close_state(), CloseState::ClosingSent);
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  server.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(client.close_state(), CloseState::Closed);
+  assert!(matches!(
+    client.read_frame().await,
+    Err(WebSocketError::ConnectionClosed)
+  ));
+}
+
+#[tokio::test]
+async fn fragments_after_close_sent() {
+  let (client, mut server) = testing::pair();
+  let mut client = FragmentCollector::new(client);
+  server.set_auto_close(false);
+
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+  server
+    .write_frame(Frame::new(
+      false,
+      OpCode::Text,
+      None,
+      b"in ".as_ref().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  server
+    .write_frame(Frame::new(
+      true,
+      OpCode::Continuation,
+      None,
+      b"flight".as_ref().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  server.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"in flight");
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(client.close_state(), CloseState::Closed);
+  assert!(matches!(
+    client.read_frame().await,
+    Err(WebSocketError::ConnectionClosed)
+  ));
+}
+
+#[tokio::test]
+async fn close_guard() {
+  let (mut client, server) = testing::pair();
+  // The server is gone, there is no one to reply to.
+  client.set_auto_close(false);
+
+  let server = CloseGuard::new(server, CloseCode::Away);
+  drop(server);
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1001u16.to_be_bytes());
+
+  let (mut client, server) = testing::pair();
+  let mut server = CloseGuard::new(server, CloseCode::Away);
+  server.set_code(CloseCode::Normal);
+  let server = server.close().await.unwrap();
+  assert!(server.is_closed());
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &1000u16.to_be_bytes());
+
+  let (mut client, server) = testing::pair();
+  drop(CloseGuard::new(server, CloseCode::Away).into_inner());
+  assert!(matches!(
+    client.read_frame().await,
+    Err(WebSocketError::ConnectionReset)
+  ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn drain() {
+  let (mut client, mut server) = testing::pair();
+
+  client
+    .write_frame(Frame::text(b"late".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::close(1001, b"gone"))
+    .await
+    .unwrap();
+
+  server.write_frame(Frame::close(1000, b"")).await.unwrap();
+  let (code, reason) = server.drain(Duration::from_secs(1)).await.unwrap();
+  assert_eq!(code, CloseCode::Away);
+  assert_eq!(reason, "gone");
+
+  let (_client, mut server) = testing::pair();
+  assert!(matches!(
+    server.drain(Duration::from_millis(20)).await,
+    Err(WebSocketError::ReadTimeout)
+  ));
+
+  let (client, mut server) = testing::pair();
+  drop(client);
+  assert!(matches!(
+    server.drain(Duration::from_secs(1)).await,
+    Err(WebSocketError::ConnectionReset)
+  ));
+}
+
+#[tokio::test]
+async fn close_empty() {
+  let (mut client, mut server) = testing::pair();
+
+  client.write_frame(Frame::close_empty()).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert!(frame.payload.is_empty());
+
+  // The echoed close frame is empty too.
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert!(frame.payload.is_empty());
+}
+
+#[tokio::test]
+async fn close_without_reason() {
+  let (mut client, mut server) = testing::pair();
+
+  client.write_frame(Frame::close(1001, b"")).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"\x03\xe9");
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"\x03\xe9");
+}
+
+#[tokio::test]
+async fn close_reply() {
+  let (mut client, mut server) = testing::pair();
+  server.set_close_reply(CloseReply::Code(CloseCode::Away, "bye".into()));
+
+  client.write_frame(Frame::close(1000, b"hi")).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], b"\x03\xe8hi");
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], b"\x03\xe9bye");
+}
@@ -0,0 +1,94 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::MessageBuilder;
+use fastwebsockets::OpCode;
+
+#[tokio::test]
+async fn compression_stats() {
+  let (mut client, mut server) = testing::pair();
+  server.set_compression(true);
+
+  let payload = b"hello ".repeat(100);
+  let message = MessageBuilder::new(OpCode::Text)
+    .chunk(payload.as_slice())
+    .compress(true);
+  client.write_message(message).await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &payload[..]);
+
+  let sent = client.compression_stats();
+  assert_eq!(sent.raw_out, payload.len() as u64);
+  assert!(sent.compressed_out < sent.raw_out);
+  assert_eq!(sent.compressed_in, 0);
+
+  let received = server.compression_stats();
+  assert_eq!(received.compressed_in, sent.compressed_out);
+  assert_eq!(received.decompressed_in, sent.raw_out);
+  assert_eq!(received.raw_out, 0);
+}
+
+#[tokio::test]
+async fn read_frame_into_compressed() {
+  let (mut client, mut server) = testing::pair();
+  server.set_compression(true);
+
+  let payload = b"hello ".repeat(100);
+  for _ in 0..2 {
+    let message = MessageBuilder::new(OpCode::Text)
+      .chunk(payload.as_slice())
+      .compress(true);
+    client.write_message(message).await.unwrap();
+  }
+
+  let mut buf = Vec::with_capacity(4096);
+  let ptr = buf.as_ptr();
+  for _ in 0..2 {
+    let header = server.read_frame_into(&mut buf).await.unwrap();
+    assert_eq!(header.opcode, OpCode::Text);
+    assert_eq!(buf, payload);
+    assert_eq!(buf.as_ptr(), ptr);
+  }
+}
+
+#[tokio::test]
+async fn compression_level() {
+  let (mut client, mut server) = testing::pair();
+  server.set_compression(true);
+  let payload = b"hello ".repeat(100);
+
+  // Level 0 only emits stored blocks, larger than the payload, so the
+  // message is sent uncompressed.
+  client.set_compression_level(0);
+  let message = MessageBuilder::new(OpCode::Binary)
+    .chunk(payload.as_slice())
+    .compress(true);
+  client.write_message(message).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &payload[..]);
+  assert_eq!(client.compression_stats().raw_out, 0);
+
+  client.set_compression_level(9);
+  let message = MessageBuilder::new(OpCode::Binary)
+    .chunk(payload.as_slice())
+    .compress(true);
+  client.write_message(message).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &payload[..]);
+  let stats = client.compression_stats();
+  assert_eq!(stats.raw_out, payload.len() as u64);
+  assert!(stats.compressed_out < stats.raw_out / 10);
+}
@@ -0,0 +1,265 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::MessageBuilder;
+use fastwebsockets::OpCode;
+use fastwebsockets::UnknownOpcode;
+use fastwebsockets::WebSocketError;
+
+#[tokio::test]
+async fn max_fragments() {
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+  server.set_max_fragments(Some(3));
+
+  let message = |fragments| {
+    (0..fragments).fold(MessageBuilder::new(OpCode::Binary), |message, _| {
+      message.chunk(b"a".as_ref())
+    })
+  };
+  client.write_message(message(3)).await.unwrap();
+  client.write_message(message(4)).await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"aaa");
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::TooManyFragments)
+  ));
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1009u16.to_be_bytes());
+}
+
+#[tokio::test]
+async fn read_message_into() {
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+  server.set_message_size_hint(64);
+
+  let message = MessageBuilder::new(OpCode::Binary)
+    .chunk(b"hello ".as_ref())
+    .chunk(b"world".as_ref());
+  client.write_message(message).await.unwrap();
+  client
+    .write_frame(Frame::text(b"single".as_ref().into()))
+    .await
+    .unwrap();
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  let mut buf = Vec::with_capacity(128);
+  let ptr = buf.as_ptr();
+  let opcode = server.read_message_into(&mut buf).await.unwrap();
+  assert_eq!(opcode, OpCode::Binary);
+  assert_eq!(buf, b"hello world");
+  assert_eq!(buf.as_ptr(), ptr);
+
+  let opcode = server.read_message_into(&mut buf).await.unwrap();
+  assert_eq!(opcode, OpCode::Text);
+  assert_eq!(buf, b"single");
+  assert_eq!(buf.as_ptr(), ptr);
+
+  assert!(matches!(
+    server.read_message_into(&mut buf).await,
+    Err(WebSocketError::ConnectionClosed)
+  ));
+}
+
+#[tokio::test]
+async fn max_message_size_fragmented() {
+  let (mut client, mut server) = testing::pair();
+  server.set_max_message_size(8);
+  let mut server = FragmentCollector::new(server);
+
+  let message = |fragments| {
+    (0..fragments).fold(MessageBuilder::new(OpCode::Binary), |message, _| {
+      message.chunk(b"aaa".as_ref())
+    })
+  };
+  client.write_message(message(2)).await.unwrap();
+  client.write_message(message(3)).await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"aaaaaa");
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::FrameTooLarge)
+  ));
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1009u16.to_be_bytes());
+}
+
+#[tokio::test]
+async fn validate_text_disabled() {
+  let (mut client, mut server) = testing::pair();
+  server.set_validate_text(false);
+
+  client
+    .write_frame(Frame::text(b"\xff\xfe".as_ref().into()))
+    .await
+    .unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"\xff\xfe");
+
+  // Fragmented text is reassembled without being checked either.
+  let mut server = FragmentCollector::new(server);
+  client
+    .write_frame(Frame::new(
+      false,
+      OpCode::Text,
+      None,
+      b"\xff".to_vec().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::new(
+      true,
+      OpCode::Continuation,
+      None,
+      b"\xfe".to_vec().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"\xff\xfe");
+}
+
+#[tokio::test]
+async fn fragment_sequences() {
+  use OpCode::*;
+
+  // The frames sent, the messages read from them, and whether reading fails
+  // afterwards.
+  type Case = (&'static [(bool, OpCode)], &'static [OpCode], bool);
+  let cases: &[Case] = &[
+    (&[(true, Text)], &[Text], false),
+    (&[(false, Text), (true, Continuation)], &[Text], false),
+    (
+      &[(false, Binary), (false, Continuation), (true, Continuation)],
+      &[Binary],
+      false,
+    ),
+    (
+      &[(false, Text), (true, Ping), (true, Continuation)],
+      &[Text],
+      false,
+    ),
+    (&[(true, Text), (true, Binary)], &[Text, Binary], false),
+    (&[(true, Continuation)], &[], true),
+    (&[(false, Continuation)], &[], true),
+    (&[(false, Text), (true, Text)], &[], true),
+    (&[(false, Text), (false, Binary)], &[], true),
+    (&[(false, Binary), (true, Text)], &[], true),
+    (&[(true, Text), (true, Continuation)], &[Text], true),
+    (
+      &[(false, Text), (false, Continuation), (true, Binary)],
+      &[],
+      true,
+    ),
+  ];
+
+  for (frames, messages, fails) in cases {
+    let (mut client, server) = testing::pair();
+    client.set_auto_close(false);
+    let mut server = FragmentCollector::new(server);
+    for &(fin, opcode) in *frames {
+      let frame = Frame::new(fin, opcode, None, b"a".to_vec().into(), false);
+      client.write_frame(frame).await.unwrap();
+    }
+
+    for &opcode in *messages {
+      assert_eq!(server.read_frame().await.unwrap().opcode, opcode);
+    }
+    if *fails {
+      assert!(matches!(
+        server.read_frame().await,
+        Err(WebSocketError::InvalidFragmentSequence)
+      ));
+      let frame = client.read_frame().await.unwrap();
+      assert_eq!(frame.opcode, Close);
+      assert_eq!(&frame.payload[..2], &1002u16.to_be_bytes());
+    }
+  }
+}
+
+#[tokio::test]
+async fn reserved_opcode_round_trip() {
+  let (mut client, mut server) = testing::pair();
+  server.set_on_unknown_opcode(UnknownOpcode::Deliver);
+
+  for value in [0x3, 0xB] {
+    let frame = Frame::new(
+      true,
+      OpCode::Other(value),
+      None,
+      b"hi".to_vec().into(),
+      false,
+    );
+    client.write_frame(frame).await.unwrap();
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, OpCode::Other(value));
+    assert_eq!(&frame.payload[..], b"hi");
+  }
+
+  // Standard opcodes have their own variant, and only 4 bits are sent.
+  for value in [0x1, 0x13] {
+    let frame =
+      Frame::new(true, OpCode::Other(value), None, Vec::new().into(), false);
+    assert!(matches!(
+      client.write_frame(frame).await,
+      Err(WebSocketError::InvalidValue)
+    ));
+  }
+}
+
+#[tokio::test]
+async fn fragmented_reserved_opcode() {
+  let (mut client, mut server) = testing::pair();
+  server.set_on_unknown_opcode(UnknownOpcode::Deliver);
+  let mut server = FragmentCollector::new(server);
+
+  let frames = [
+    (false, OpCode::Other(0x3), "a"),
+    (true, OpCode::Continuation, "b"),
+    (false, OpCode::Text, "c"),
+    (true, OpCode::Continuation, "d"),
+  ];
+  for (fin, opcode, payload) in frames {
+    let frame = Frame::new(fin, opcode, None, payload.as_bytes().into(), false);
+    client.write_frame(frame).await.unwrap();
+  }
+
+  // The reserved opcode message is returned frame by frame, the next one is
+  // reassembled.
+  for (fin, opcode, payload) in [
+    (false, OpCode::Other(0x3), "a"),
+    (true, OpCode::Continuation, "b"),
+    (true, OpCode::Text, "cd"),
+  ] {
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!((frame.fin, frame.opcode), (fin, opcode));
+    assert_eq!(&frame.payload[..], payload.as_bytes());
+  }
+}
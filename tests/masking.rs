@@ -0,0 +1,115 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::testing::RecordingStream;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+
+#[tokio::test]
+async fn forward_masked_frames() {
+  let (stream, peer) = tokio::io::duplex(1024);
+  let mut client =
+    WebSocket::after_handshake(RecordingStream::new(stream), Role::Client);
+  let mut proxy = WebSocket::after_handshake(peer, Role::Server);
+  proxy.set_auto_apply_mask(false);
+  let (stream, _upstream) = tokio::io::duplex(1024);
+  let mut forward =
+    WebSocket::after_handshake(RecordingStream::new(stream), Role::Client);
+  forward.set_auto_apply_mask(false);
+
+  client
+    .write_frame(Frame::text("héllo".as_bytes().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::close(1000, b"bye"))
+    .await
+    .unwrap();
+  for _ in 0..2 {
+    let frame = proxy.read_frame().await.unwrap();
+    assert!(frame.mask_key().is_some());
+    forward.write_frame(frame).await.unwrap();
+  }
+  assert_eq!(forward.get_ref().written(), client.get_ref().written());
+
+  // The payload is still validated.
+  let (mut client, mut proxy) = testing::pair();
+  proxy.set_auto_apply_mask(false);
+  client
+    .write_frame(Frame::text(b"\xff".as_ref().into()))
+    .await
+    .unwrap();
+  assert!(matches!(
+    proxy.read_frame().await,
+    Err(WebSocketError::InvalidUTF8)
+  ));
+}
+
+#[tokio::test]
+async fn auto_apply_mask_disabled_pong() {
+  let (mut client, mut proxy) = testing::pair();
+  proxy.set_auto_apply_mask(false);
+  client
+    .write_frame(Frame::new(
+      true,
+      OpCode::Ping,
+      None,
+      b"hello".as_ref().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::binary(b"data".as_ref().into()))
+    .await
+    .unwrap();
+  proxy.read_frame().await.unwrap();
+
+  // The pong echoes the ping payload, not its masked bytes.
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Pong);
+  assert_eq!(&frame.payload[..], b"hello");
+}
+
+#[tokio::test]
+async fn auto_apply_mask_disabled_fragments() {
+  let (mut client, mut proxy) = testing::pair();
+  proxy.set_auto_apply_mask(false);
+  let mut proxy = FragmentCollector::new(proxy);
+
+  // The message is split in the middle of a character, each fragment
+  // masked with its own key.
+  let text = "héllo".as_bytes();
+  let frames = [
+    (false, OpCode::Text, &text[..2]),
+    (true, OpCode::Continuation, &text[2..]),
+    (true, OpCode::Binary, b"data".as_ref()),
+  ];
+  for (fin, opcode, payload) in frames {
+    let frame = Frame::new(fin, opcode, None, payload.into(), false);
+    client.write_frame(frame).await.unwrap();
+  }
+
+  let frame = proxy.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], text);
+  let frame = proxy.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Binary);
+  assert_eq!(&frame.payload[..], b"data");
+}
@@ -0,0 +1,363 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::testing::RecordingStream;
+use fastwebsockets::CloseCode;
+use fastwebsockets::Event;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::MessageBuilder;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+#[tokio::test]
+async fn read_buffered_frames() {
+  let (mut client, mut server) = testing::pair();
+
+  for payload in [b"one", b"two", b"six"] {
+    client
+      .write_frame(Frame::binary(payload.as_ref().into()))
+      .await
+      .unwrap();
+  }
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"one");
+
+  let frames = server.read_buffered_frames().await.unwrap();
+  assert_eq!(frames.len(), 2);
+  assert_eq!(&frames[0].payload[..], b"two");
+  assert_eq!(&frames[1].payload[..], b"six");
+
+  assert!(server.read_buffered_frames().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn buffered_len() {
+  let (mut client, mut server) = testing::pair();
+  assert_eq!(server.buffered_len(), 0);
+  assert!(!server.has_buffered_frame());
+
+  for payload in [b"one", b"two"] {
+    client
+      .write_frame(Frame::binary(payload.as_ref().into()))
+      .await
+      .unwrap();
+  }
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"one");
+  // Client frames carry a 4 byte mask.
+  assert_eq!(server.buffered_len(), 2 + 4 + 3);
+  assert!(server.has_buffered_frame());
+
+  server.read_frame().await.unwrap();
+  assert_eq!(server.buffered_len(), 0);
+  assert!(!server.has_buffered_frame());
+}
+
+#[tokio::test]
+async fn reserved_bits() {
+  let (mut client, mut server) = testing::pair();
+
+  let mut frame = Frame::binary(b"ext".as_ref().into());
+  frame.rsv2 = true;
+  client.write_frame(frame).await.unwrap();
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::ReservedBitsNotZero)
+  ));
+
+  let (mut client, mut server) = testing::pair();
+  server.set_allow_reserved_bits(true);
+  let mut frame = Frame::binary(b"ext".as_ref().into());
+  frame.rsv3 = true;
+  client.write_frame(frame).await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert!(!frame.rsv2);
+  assert!(frame.rsv3);
+  assert_eq!(&frame.payload[..], b"ext");
+}
+
+#[tokio::test]
+async fn zero_length_frames() {
+  let (stream, _peer) = tokio::io::duplex(1024);
+  let mut ws =
+    WebSocket::after_handshake(RecordingStream::new(stream), Role::Server);
+  ws.write_frame(Frame::binary(Vec::new().into()))
+    .await
+    .unwrap();
+  ws.write_frame(Frame::text(Vec::new().into()))
+    .await
+    .unwrap();
+  assert_eq!(ws.into_inner().written(), &[0x82, 0x00, 0x81, 0x00]);
+
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+  for opcode in [OpCode::Text, OpCode::Binary] {
+    let message = (0..3).fold(MessageBuilder::new(opcode), |message, _| {
+      message.chunk(Vec::new())
+    });
+    client.write_message(message).await.unwrap();
+    client
+      .write_frame(Frame::new(true, opcode, None, Vec::new().into(), false))
+      .await
+      .unwrap();
+  }
+
+  for opcode in [OpCode::Text, OpCode::Text, OpCode::Binary, OpCode::Binary] {
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.opcode, opcode);
+    assert!(frame.fin);
+    assert!(frame.payload.is_empty());
+  }
+}
+
+#[tokio::test(start_paused = true)]
+async fn cancel_read_mid_frame() {
+  let (mut peer, stream) = tokio::io::duplex(1024);
+  let mut server = WebSocket::after_handshake(stream, Role::Server);
+
+  // Half of the header of a masked "Hello" text frame, then half of its
+  // payload, with the read cancelled after each part.
+  for part in [b"\x81\x85\x00\x00".as_ref(), b"\x00\x00Hel"] {
+    peer.write_all(part).await.unwrap();
+    tokio::select! {
+      _ = server.read_frame() => panic!("frame is incomplete"),
+      _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+    }
+  }
+  peer.write_all(b"lo").await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hello");
+}
+
+#[tokio::test]
+async fn read_frame_into() {
+  let (mut client, mut server) = testing::pair();
+
+  client
+    .write_frame(Frame::binary(b"first frame".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::text(b"second".as_ref().into()))
+    .await
+    .unwrap();
+
+  let mut buf = Vec::with_capacity(64);
+  let ptr = buf.as_ptr();
+  let header = server.read_frame_into(&mut buf).await.unwrap();
+  assert_eq!(header.opcode, OpCode::Binary);
+  assert!(header.fin);
+  assert_eq!(header.length, 11);
+  assert_eq!(buf, b"first frame");
+
+  let header = server.read_frame_into(&mut buf).await.unwrap();
+  assert_eq!(header.opcode, OpCode::Text);
+  assert_eq!(buf, b"second");
+  assert_eq!(buf.as_ptr(), ptr);
+}
+
+#[tokio::test]
+async fn read_frame_bytes() {
+  let (mut client, mut server) = testing::pair();
+
+  client
+    .write_frame(Frame::binary(b"hello world".as_ref().into()))
+    .await
+    .unwrap();
+
+  let (header, payload) = server.read_frame_bytes().await.unwrap();
+  assert_eq!(header.opcode, OpCode::Binary);
+  assert_eq!(header.length, 11);
+  assert_eq!(payload.slice(6..), b"world".as_ref());
+}
+
+#[tokio::test]
+async fn read_event() {
+  let (mut client, mut server) = testing::pair();
+  server.set_auto_pong(false);
+  server.set_auto_close(false);
+
+  client
+    .write_frame(Frame::text(b"data".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::new(
+      true,
+      OpCode::Ping,
+      None,
+      b"ping".as_ref().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::close(1001, b"gone"))
+    .await
+    .unwrap();
+
+  let Event::Data(frame) = server.read_event().await.unwrap() else {
+    panic!("expected data");
+  };
+  assert_eq!(&frame.payload[..], b"data");
+  let Event::Ping(payload) = server.read_event().await.unwrap() else {
+    panic!("expected ping");
+  };
+  assert_eq!(&payload[..], b"ping");
+  let Event::Close(code, reason) = server.read_event().await.unwrap() else {
+    panic!("expected close");
+  };
+  assert_eq!(code, CloseCode::Away);
+  assert_eq!(reason, "gone");
+}
+
+#[tokio::test]
+async fn peek_opcode() {
+  let (mut client, mut server) = testing::pair();
+
+  client
+    .write_frame(Frame::binary(b"data".as_ref().into()))
+    .await
+    .unwrap();
+  client.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  assert_eq!(server.peek_opcode().await.unwrap(), OpCode::Binary);
+  assert_eq!(server.peek_opcode().await.unwrap(), OpCode::Binary);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"data");
+
+  assert_eq!(server.peek_opcode().await.unwrap(), OpCode::Close);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+}
+
+#[tokio::test]
+async fn prepend_buffer() {
+  let (mut peer, stream) = tokio::io::duplex(1024);
+  let mut server = WebSocket::after_handshake(stream, Role::Server);
+
+  // A masked "Hi" text frame, split between the prepended bytes and the
+  // stream.
+  server.prepend_buffer(b"\x81\x82\x00\x00".to_vec());
+  peer.write_all(b"\x00\x00Hi").await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hi");
+}
+
+#[tokio::test]
+async fn forward_pongs() {
+  let (mut client, mut server) = testing::pair();
+  server.set_forward_pongs(false);
+
+  let pong = Frame::pong(b"unsolicited".as_ref().into());
+  client.write_frame(pong).await.unwrap();
+  client
+    .write_frame(Frame::text(b"data".as_ref().into()))
+    .await
+    .unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"data");
+}
+
+#[tokio::test]
+async fn read_message() {
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+
+  client
+    .write_frame(Frame::new(
+      false,
+      OpCode::Binary,
+      None,
+      vec![1, 2].into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::pong(b"pong".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::new(
+      true,
+      OpCode::Continuation,
+      None,
+      vec![3].into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::text(b"hello".as_ref().into()))
+    .await
+    .unwrap();
+  client.write_frame(Frame::close_empty()).await.unwrap();
+
+  let (opcode, payload) = server.read_message().await.unwrap();
+  assert_eq!(opcode, OpCode::Binary);
+  assert_eq!(payload, vec![1, 2, 3]);
+  let (opcode, payload) = server.read_message().await.unwrap();
+  assert_eq!(opcode, OpCode::Text);
+  assert_eq!(payload, b"hello");
+  assert!(matches!(
+    server.read_message().await,
+    Err(WebSocketError::ConnectionClosed)
+  ));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn read_across_threads() {
+  let (mut peer, stream) = tokio::io::duplex(1024);
+  let mut server = WebSocket::after_handshake(stream, Role::Server);
+
+  let reader = tokio::spawn(async move {
+    let mut payloads = Vec::new();
+    for _ in 0..32 {
+      let frame = server.read_frame().await.unwrap();
+      payloads.push(frame.payload.to_vec());
+    }
+    payloads
+  });
+
+  // Every frame arrives in pieces, so the read is suspended mid-frame and
+  // may resume on any worker thread.
+  let mut expected = Vec::new();
+  for i in 0..32u8 {
+    let payload = vec![i; 200];
+    let mut bytes = vec![0x82, 126, 0, 200];
+    bytes.extend_from_slice(&payload);
+    for chunk in bytes.chunks(64) {
+      peer.write_all(chunk).await.unwrap();
+      tokio::task::yield_now().await;
+    }
+    expected.push(payload);
+  }
+
+  assert_eq!(reader.await.unwrap(), expected);
+}
@@ -0,0 +1,171 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::Backoff;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::ReconnectEvent;
+use fastwebsockets::ReconnectingWebSocket;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+use std::time::Duration;
+
+#[tokio::test]
+async fn reconnecting_websocket() {
+  let (servers_tx, mut servers) = tokio::sync::mpsc::unbounded_channel();
+  let connect = || {
+    let (client, server) = testing::pair();
+    servers_tx.send(server).unwrap();
+    async { Ok(client) }
+  };
+  let backoff = Backoff {
+    initial: Duration::from_millis(1),
+    ..Backoff::default()
+  };
+  let mut client = ReconnectingWebSocket::connect(connect, backoff)
+    .await
+    .unwrap();
+
+  // The first server goes away.
+  drop(servers.recv().await.unwrap());
+  assert!(matches!(
+    client.read_frame().await.unwrap(),
+    ReconnectEvent::Reconnected
+  ));
+
+  let mut server = servers.recv().await.unwrap();
+  let reconnected = client
+    .write_frame(Frame::text(b"again".as_ref().into()))
+    .await
+    .unwrap();
+  assert!(!reconnected);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"again");
+
+  server
+    .write_frame(Frame::binary(b"hi".as_ref().into()))
+    .await
+    .unwrap();
+  let ReconnectEvent::Frame(frame) = client.read_frame().await.unwrap() else {
+    panic!("expected a frame");
+  };
+  assert_eq!(&frame.payload[..], b"hi");
+}
+
+#[tokio::test]
+async fn reconnecting_websocket_fragments() {
+  let (servers_tx, mut servers) = tokio::sync::mpsc::unbounded_channel();
+  let connect = || {
+    let (client, server) = testing::pair();
+    servers_tx.send(server).unwrap();
+    async { Ok(client) }
+  };
+  let backoff = Backoff {
+    initial: Duration::from_millis(1),
+    ..Backoff::default()
+  };
+  let mut client = ReconnectingWebSocket::connect(connect, backoff)
+    .await
+    .unwrap();
+
+  // The connection drops in the middle of a message.
+  let mut first =
+    Frame::new(false, OpCode::Text, None, b"a".as_ref().into(), false);
+  first.rsv2 = true;
+  client.write_frame(first).await.unwrap();
+  drop(servers.recv().await.unwrap());
+  let last = Frame::new(
+    true,
+    OpCode::Continuation,
+    None,
+    b"b".as_ref().into(),
+    false,
+  );
+  assert!(client.write_frame(last).await.unwrap());
+
+  // The message is sent again from its first fragment, reserved bits
+  // included.
+  let mut server = servers.recv().await.unwrap();
+  server.set_allow_reserved_bits(true);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!((frame.fin, frame.opcode), (false, OpCode::Text));
+  assert!(frame.rsv2);
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!((frame.fin, frame.opcode), (true, OpCode::Continuation));
+  assert_eq!(&frame.payload[..], b"b");
+}
+
+#[tokio::test]
+async fn reconnecting_websocket_invalid_frame() {
+  let (servers_tx, mut servers) = tokio::sync::mpsc::unbounded_channel();
+  let connect = || {
+    let (client, server) = testing::pair();
+    servers_tx.send(server).unwrap();
+    async { Ok(client) }
+  };
+  let backoff = Backoff {
+    initial: Duration::from_millis(1),
+    ..Backoff::default()
+  };
+  let mut client = ReconnectingWebSocket::connect(connect, backoff)
+    .await
+    .unwrap();
+
+  let mut server = servers.recv().await.unwrap();
+  server.write_frame(Frame::close(1000, &[])).await.unwrap();
+  let ReconnectEvent::Frame(frame) = client.read_frame().await.unwrap() else {
+    panic!("expected a frame");
+  };
+  assert_eq!(frame.opcode, OpCode::Close);
+
+  // The new connection is kept although the frame can not be written.
+  let invalid =
+    Frame::new(true, OpCode::Other(0x13), None, Vec::new().into(), false);
+  assert!(matches!(
+    client.write_frame(invalid).await,
+    Err(WebSocketError::InvalidValue)
+  ));
+  let mut server = servers.recv().await.unwrap();
+  let reconnected = client
+    .write_frame(Frame::text(b"hi".as_ref().into()))
+    .await
+    .unwrap();
+  assert!(!reconnected);
+  assert_eq!(&server.read_frame().await.unwrap().payload[..], b"hi");
+  assert!(servers.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn reconnecting_websocket_max_retries() {
+  let mut attempts = 0;
+  let connect = || {
+    attempts += 1;
+    async {
+      Err::<WebSocket<tokio::io::DuplexStream>, _>(
+        WebSocketError::UnexpectedEOF,
+      )
+    }
+  };
+  let backoff = Backoff {
+    initial: Duration::from_millis(1),
+    max_retries: Some(3),
+    ..Backoff::default()
+  };
+  assert!(matches!(
+    ReconnectingWebSocket::connect(connect, backoff).await,
+    Err(WebSocketError::UnexpectedEOF)
+  ));
+  assert_eq!(attempts, 4);
+}
@@ -0,0 +1,166 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::CloseState;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::WebSocketStream;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+
+#[tokio::test]
+async fn websocket_stream() {
+  let (client, mut server) = testing::pair();
+  let mut stream = WebSocketStream::new(client);
+
+  stream.write_all(b"hello").await.unwrap();
+  stream.flush().await.unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Binary);
+  assert_eq!(&frame.payload[..], b"hello");
+
+  server
+    .write_frame(Frame::binary(b"abc".as_ref().into()))
+    .await
+    .unwrap();
+  server
+    .write_frame(Frame::new(
+      true,
+      OpCode::Ping,
+      None,
+      b"ping".as_ref().into(),
+      false,
+    ))
+    .await
+    .unwrap();
+  server
+    .write_frame(Frame::binary(b"def".as_ref().into()))
+    .await
+    .unwrap();
+  server.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  let mut buf = Vec::new();
+  stream.read_to_end(&mut buf).await.unwrap();
+  assert_eq!(buf, b"abcdef");
+
+  // The ping was answered, and the close frame echoed.
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Pong);
+  assert_eq!(&frame.payload[..], b"ping");
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+}
+
+#[tokio::test]
+async fn read_frame_streaming() {
+  let (mut client, mut server) = testing::pair_with_capacity(4096);
+  let payload: Vec<u8> = (0..1_000_000).map(|i| i as u8).collect();
+  let expected = payload.clone();
+  let writer = tokio::spawn(async move {
+    client
+      .write_frame(Frame::binary(payload.into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::binary(vec![1; 10_000].into()))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        false,
+        OpCode::Text,
+        None,
+        "caf\u{e9} ".as_bytes().into(),
+        false,
+      ))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::new(
+        true,
+        OpCode::Continuation,
+        None,
+        b"au lait".as_ref().into(),
+        false,
+      ))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(
+        "h\u{e9}llo w\u{f6}rld \u{1f600}".as_bytes().into(),
+      ))
+      .await
+      .unwrap();
+    client
+      .write_frame(Frame::text(b"\xff".as_ref().into()))
+      .await
+      .unwrap();
+    client
+  });
+
+  // The payload is read through the reader, in chunks.
+  let (header, mut reader) = server.read_frame_streaming().await.unwrap();
+  assert_eq!(header.opcode, OpCode::Binary);
+  assert_eq!(header.length, 1_000_000);
+  assert_eq!(reader.remaining(), 1_000_000);
+  let mut received = Vec::new();
+  let mut chunk = [0; 1000];
+  loop {
+    let n = reader.read(&mut chunk).await.unwrap();
+    if n == 0 {
+      break;
+    }
+    received.extend_from_slice(&chunk[..n]);
+  }
+  assert_eq!(received, expected);
+
+  // The rest of a payload that is not read is skipped.
+  let (_, mut reader) = server.read_frame_streaming().await.unwrap();
+  reader.read_exact(&mut chunk[..10]).await.unwrap();
+  drop(reader);
+
+  let mut text = String::new();
+  for fin in [false, true] {
+    let (header, mut reader) = server.read_frame_streaming().await.unwrap();
+    assert_eq!(header.fin, fin);
+    reader.read_to_string(&mut text).await.unwrap();
+  }
+  assert_eq!(text, "caf\u{e9} au lait");
+
+  // Characters split between reads are validated once complete.
+  let (_, mut reader) = server.read_frame_streaming().await.unwrap();
+  let mut text = Vec::new();
+  while reader.read(&mut chunk[..1]).await.unwrap() == 1 {
+    text.push(chunk[0]);
+  }
+  assert_eq!(text, "h\u{e9}llo w\u{f6}rld \u{1f600}".as_bytes());
+
+  let (_, mut reader) = server.read_frame_streaming().await.unwrap();
+  let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+  // Control frames are read whole.
+  let mut client = writer.await.unwrap();
+  client
+    .write_frame(Frame::close(1000, b"bye"))
+    .await
+    .unwrap();
+  let (header, mut reader) = server.read_frame_streaming().await.unwrap();
+  assert_eq!(header.opcode, OpCode::Close);
+  let mut payload = Vec::new();
+  reader.read_to_end(&mut payload).await.unwrap();
+  assert_eq!(payload, b"\x03\xe8bye");
+  assert_eq!(server.close_state(), CloseState::Closed);
+}
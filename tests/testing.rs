@@ -0,0 +1,64 @@
+use fastwebsockets::testing;
+use fastwebsockets::testing::RecordingStream;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+
+#[tokio::test]
+async fn pair() {
+  let (mut client, mut server) = testing::pair();
+
+  client
+    .write_frame(Frame::text(b"Hello!".as_ref().into()))
+    .await
+    .unwrap();
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hello!");
+
+  server
+    .write_frame(Frame::binary(b"Goodbye!".as_ref().into()))
+    .await
+    .unwrap();
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Binary);
+  assert_eq!(&frame.payload[..], b"Goodbye!");
+}
+
+#[test]
+fn fuzz_decode() {
+  let (frames, e) = testing::fuzz_decode(
+    b"\x81\x82\x00\x00\x00\x00Hi\x82\x80\x00\x00\x00\x00\x82\x81",
+  );
+  assert_eq!(frames.len(), 2);
+  assert_eq!(frames[0].opcode, OpCode::Text);
+  assert_eq!(&frames[0].payload[..], b"Hi");
+  assert_eq!(frames[1].opcode, OpCode::Binary);
+  assert!(matches!(e, WebSocketError::UnexpectedEOF));
+
+  let (frames, e) = testing::fuzz_decode(b"\x81\x82\x00\x00\x00\x00\xff\xfe");
+  assert!(frames.is_empty());
+  assert!(matches!(e, WebSocketError::InvalidUTF8));
+}
+
+#[tokio::test]
+async fn recording_stream() {
+  let (stream, _peer) = tokio::io::duplex(1024);
+  let stream = RecordingStream::new(stream);
+  let mut ws = WebSocket::after_handshake(stream, Role::Server);
+
+  ws.write_frame(Frame::text(b"Hi".as_ref().into()))
+    .await
+    .unwrap();
+
+  assert_eq!(ws.get_ref().written(), &[0x81, 0x02, b'H', b'i']);
+  ws.write_frame(Frame::close(1000, b"")).await.unwrap();
+
+  let stream = ws.into_inner();
+  assert_eq!(
+    stream.written(),
+    &[0x81, 0x02, b'H', b'i', 0x88, 0x02, 0x03, 0xe8]
+  );
+}
@@ -0,0 +1,198 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::OpCode;
+use fastwebsockets::RateLimit;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
+
+#[tokio::test(start_paused = true)]
+async fn message_timeout() {
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+  server.set_message_timeout(Some(Duration::from_millis(100)));
+
+  let fragment =
+    |opcode| Frame::new(false, opcode, None, vec![0].into(), false);
+  let writer = tokio::spawn(async move {
+    client.write_frame(fragment(OpCode::Binary)).await.unwrap();
+    for _ in 0..3 {
+      tokio::time::sleep(Duration::from_millis(40)).await;
+      client
+        .write_frame(fragment(OpCode::Continuation))
+        .await
+        .unwrap();
+    }
+    client
+  });
+
+  // Fragments keep arriving, but the message is not complete in time.
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::MessageTimeout)
+  ));
+  let mut client = writer.await.unwrap();
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+  assert_eq!(&frame.payload[..], &1008u16.to_be_bytes());
+}
+
+#[tokio::test(start_paused = true)]
+async fn message_timeout_without_auto_close() {
+  let (mut client, mut server) = testing::pair();
+  server.set_auto_close(false);
+  let mut server = FragmentCollector::new(server);
+  server.set_message_timeout(Some(Duration::from_millis(10)));
+
+  let fragment = Frame::new(false, OpCode::Binary, None, vec![0].into(), false);
+  client.write_frame(fragment).await.unwrap();
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::MessageTimeout)
+  ));
+  // No close frame was sent.
+  assert_eq!(server.write_stats().bytes_written, 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn close_reply_timeout() {
+  let (mut client, mut server) = testing::pair_with_capacity(64);
+  server.set_close_reply_timeout(Some(Duration::from_millis(50)));
+
+  // Fill the pipe to the client, which never reads it.
+  server
+    .write_frame(Frame::binary(vec![0; 60].into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::close(1000, &[b'a'; 40]))
+    .await
+    .unwrap();
+
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::WriteTimeout)
+  ));
+  assert!(server.is_closed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_frame_deadline() {
+  let (mut peer, stream) = tokio::io::duplex(1024);
+  let mut server = WebSocket::after_handshake(stream, Role::Server);
+
+  // Header and half of the payload.
+  peer.write_all(&[0x82, 0x04, b'a', b'b']).await.unwrap();
+  let deadline = Instant::now() + Duration::from_millis(50);
+  assert!(matches!(
+    server.read_frame_deadline(deadline).await,
+    Err(WebSocketError::ReadTimeout)
+  ));
+
+  peer.write_all(b"cd").await.unwrap();
+  let deadline = Instant::now() + Duration::from_secs(5);
+  let frame = server.read_frame_deadline(deadline).await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Binary);
+  assert_eq!(&frame.payload[..], b"abcd");
+}
+
+#[tokio::test(start_paused = true)]
+async fn write_frame_deadline() {
+  let (mut client, _server) = testing::pair_with_capacity(16);
+
+  let deadline = Instant::now() + Duration::from_millis(50);
+  let frame = Frame::binary(vec![0; 1024].into());
+  assert!(matches!(
+    client.write_frame_deadline(frame, deadline).await,
+    Err(WebSocketError::WriteTimeout)
+  ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn idle_timeout() {
+  let (mut client, mut server) = testing::pair();
+  server.set_idle_timeout(Some(Duration::from_millis(100)));
+
+  // The ping keeps the connection alive past the idle timeout.
+  let client = tokio::spawn(async move {
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let ping = Frame::new(true, OpCode::Ping, None, b"".as_ref().into(), false);
+    client.write_frame(ping).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    client
+      .write_frame(Frame::text(b"hi".as_ref().into()))
+      .await
+      .unwrap();
+    client
+  });
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"hi");
+
+  // Writing resets the timer too.
+  tokio::time::sleep(Duration::from_millis(60)).await;
+  server
+    .write_frame(Frame::text(b"still here".as_ref().into()))
+    .await
+    .unwrap();
+  let start = Instant::now();
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::IdleTimeout)
+  ));
+  assert_eq!(start.elapsed(), Duration::from_millis(100));
+  drop(client.await.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn read_rate_limit() {
+  let (mut client, mut server) = testing::pair();
+  server.set_read_rate_limit(Some(RateLimit {
+    frames_per_second: Some(10),
+    max_throttle: Some(Duration::from_millis(250)),
+    ..Default::default()
+  }));
+  for _ in 0..20 {
+    client
+      .write_frame(Frame::binary(b"x".as_ref().into()))
+      .await
+      .unwrap();
+  }
+
+  // A burst of 10 frames goes through, the next ones at 10 per second.
+  let start = Instant::now();
+  for _ in 0..13 {
+    server.read_frame().await.unwrap();
+  }
+  assert!(start.elapsed() >= Duration::from_millis(200));
+
+  // After being throttled for longer than allowed, the peer is disconnected.
+  assert!(matches!(
+    server.read_frame().await,
+    Err(WebSocketError::RateLimitExceeded)
+  ));
+  loop {
+    let frame = client.read_frame().await.unwrap();
+    if frame.opcode == OpCode::Close {
+      assert_eq!(&frame.payload[..2], &1008u16.to_be_bytes());
+      break;
+    }
+  }
+}
@@ -0,0 +1,205 @@
+// Copyright 2023 Divy Srivastava <dj.srivastava23@gmail.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use fastwebsockets::testing;
+use fastwebsockets::testing::RecordingStream;
+use fastwebsockets::FragmentCollector;
+use fastwebsockets::Frame;
+use fastwebsockets::MessageBuilder;
+use fastwebsockets::OpCode;
+use fastwebsockets::Role;
+use fastwebsockets::WebSocket;
+use fastwebsockets::WebSocketError;
+use fastwebsockets::WriteStats;
+
+#[tokio::test]
+async fn write_frame_with_progress() {
+  let (mut client, mut server) = testing::pair();
+
+  let mut progress = Vec::new();
+  let frame = Frame::binary(vec![7; 10_000].into());
+  client
+    .write_frame_with_progress(frame, 4096, |written, total| {
+      progress.push((written, total))
+    })
+    .await
+    .unwrap();
+  // The header of a masked frame with a 16 bit length is 8 bytes long.
+  assert_eq!(
+    progress,
+    [
+      (8, 10_008),
+      (4104, 10_008),
+      (8200, 10_008),
+      (10_008, 10_008)
+    ]
+  );
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &[7; 10_000]);
+}
+
+#[tokio::test]
+async fn write_stats() {
+  let (mut client, _server) = testing::pair();
+  client.set_writev_threshold(16);
+
+  client
+    .write_frame(Frame::text(b"small".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::binary(vec![0; 100].into()))
+    .await
+    .unwrap();
+  assert_eq!(
+    client.write_stats(),
+    WriteStats {
+      vectored_frames: 1,
+      copied_frames: 1,
+      split_frames: 0,
+      bytes_written: (2 + 4 + 5) + (2 + 4 + 100),
+    }
+  );
+}
+
+#[tokio::test]
+async fn copy_threshold() {
+  let (mut client, mut server) = testing::pair();
+  client.set_writev(false);
+  client.set_copy_threshold(Some(16));
+
+  client
+    .write_frame(Frame::text(b"small".as_ref().into()))
+    .await
+    .unwrap();
+  client
+    .write_frame(Frame::binary(vec![7; 100].into()))
+    .await
+    .unwrap();
+  assert_eq!(
+    client.write_stats(),
+    WriteStats {
+      vectored_frames: 0,
+      copied_frames: 1,
+      split_frames: 1,
+      bytes_written: (2 + 4 + 5) + (2 + 4 + 100),
+    }
+  );
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], b"small");
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(&frame.payload[..], &[7; 100]);
+}
+
+#[tokio::test]
+async fn write_raw() {
+  let (mut client, mut server) = testing::pair();
+
+  // A text frame and a close frame, as a server sends them.
+  let bytes = b"\x81\x02Hi\x88\x02\x03\xe8";
+  server.write_raw(bytes).await.unwrap();
+  assert!(server.is_closed());
+  assert!(matches!(
+    server.write_raw(b"\x81\x00").await,
+    Err(WebSocketError::ConnectionClosed)
+  ));
+  // Repeated close frames are skipped, as with `write_frame`.
+  server.write_raw(b"\x88\x02\x03\xe8").await.unwrap();
+  assert_eq!(server.write_stats().bytes_written, bytes.len() as u64);
+
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hi");
+  let frame = client.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Close);
+}
+
+#[tokio::test]
+async fn write_message() {
+  let (mut client, server) = testing::pair();
+  let mut server = FragmentCollector::new(server);
+
+  let message = MessageBuilder::new(OpCode::Text)
+    .chunk(b"Hello, ".as_ref())
+    .chunk(b"world".as_ref())
+    .chunk(b"!".as_ref());
+  client.write_message(message).await.unwrap();
+
+  let frame = server.read_frame().await.unwrap();
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hello, world!");
+}
+
+#[tokio::test]
+async fn send_message() {
+  let (mut client, mut server) = testing::pair();
+  client.set_fragment_size(Some(5));
+
+  client.send_text("Hello, world!").await.unwrap();
+  let expected = [
+    (false, OpCode::Text, b"Hello".as_ref()),
+    (false, OpCode::Continuation, b", wor".as_ref()),
+    (true, OpCode::Continuation, b"ld!".as_ref()),
+  ];
+  for (fin, opcode, payload) in expected {
+    let frame = server.read_frame().await.unwrap();
+    assert_eq!(frame.fin, fin);
+    assert_eq!(frame.opcode, opcode);
+    assert_eq!(&frame.payload[..], payload);
+  }
+
+  client.send_binary(&[1, 2, 3, 4, 5, 6]).await.unwrap();
+  let mut server = FragmentCollector::new(server);
+  let (opcode, payload) = server.read_message().await.unwrap();
+  assert_eq!(opcode, OpCode::Binary);
+  assert_eq!(payload, [1, 2, 3, 4, 5, 6]);
+
+  server.send_text("Hi").await.unwrap();
+  let frame = client.read_frame().await.unwrap();
+  assert!(frame.fin);
+  assert_eq!(frame.opcode, OpCode::Text);
+  assert_eq!(&frame.payload[..], b"Hi");
+}
+
+#[tokio::test]
+async fn cork() {
+  let (stream, _peer) = tokio::io::duplex(1024);
+  let mut ws =
+    WebSocket::after_handshake(RecordingStream::new(stream), Role::Server);
+
+  ws.cork();
+  ws.write_frame(Frame::text(b"a".as_ref().into()))
+    .await
+    .unwrap();
+  ws.write_message(MessageBuilder::new(OpCode::Binary).chunk(b"b".as_ref()))
+    .await
+    .unwrap();
+  ws.write_raw(&[0x8A, 0x00]).await.unwrap();
+  assert!(ws.get_ref().written().is_empty());
+
+  ws.uncork().await.unwrap();
+  assert_eq!(
+    ws.get_ref().written(),
+    &[0x81, 0x01, b'a', 0x82, 0x01, b'b', 0x8A, 0x00]
+  );
+  assert_eq!(ws.write_stats().bytes_written, 8);
+
+  // Frames are written right away again.
+  ws.write_frame(Frame::text(b"c".as_ref().into()))
+    .await
+    .unwrap();
+  assert_eq!(ws.get_ref().written().len(), 11);
+}